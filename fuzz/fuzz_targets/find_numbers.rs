@@ -0,0 +1,29 @@
+//! Differential fuzzing of the hand-written number scanner against the
+//! `NUM_PATTERN` regex it replaced.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use libfuzzer_sys::fuzz_target;
+use regex::Regex;
+
+/// The original pattern: a float literal not preceded by `[A-Za-z_#]`.
+static NUM_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:^|[^A-Za-z_#])(-?(?:[0-9]+\.[0-9]*(?:[eE][+-]?[0-9]+)?|[0-9]+[eE][+-]?[0-9]+|\.[0-9]+(?:[eE][+-]?[0-9]+)?))",
+    )
+    .unwrap()
+});
+
+fuzz_target!(|data: &str| {
+    let expected: Vec<_> = NUM_PATTERN
+        .captures_iter(data)
+        .map(|caps| caps.get(1).unwrap().range())
+        .collect();
+    assert_eq!(
+        stepreduce::fuzzing::find_numbers(data),
+        expected,
+        "input: {data:?}"
+    );
+});
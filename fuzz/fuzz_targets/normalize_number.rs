@@ -0,0 +1,129 @@
+//! Check `normalize_number` and `round_number` against exact decimal
+//! arithmetic on every number literal in the input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stepreduce::{Precision, fuzzing};
+
+/// Literals with larger exponents are left unchanged by the normalizer.
+const MAX_EXPANDED_EXPONENT: i64 = 4096;
+
+/// An exact decimal: `digits × 10^exp`, where `digits` has neither leading
+/// nor trailing zeros. Zero is `(false, "", 0)`.
+#[derive(Debug, PartialEq, Eq)]
+struct Decimal {
+    negative: bool,
+    digits: String,
+    exp: i64,
+}
+
+impl Decimal {
+    fn new(negative: bool, digits: &str, exp: i64) -> Self {
+        let digits = digits.trim_start_matches('0');
+        let trimmed = digits.trim_end_matches('0');
+        if trimmed.is_empty() {
+            return Self {
+                negative: false,
+                digits: String::new(),
+                exp: 0,
+            };
+        }
+        Self {
+            negative,
+            digits: trimmed.to_string(),
+            exp: exp + (digits.len() - trimmed.len()) as i64,
+        }
+    }
+
+    /// Parse a literal as matched by the number scanner.
+    fn parse(s: &str) -> Option<Self> {
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(e) => (&s[..e], s[e + 1..].parse::<i64>().ok()?),
+            None => (s, 0),
+        };
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, mantissa),
+        };
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = format!("{int_part}{frac_part}");
+        Some(Self::new(negative, &digits, exp - frac_part.len() as i64))
+    }
+
+    /// Truncate towards zero to `n` fractional digits.
+    fn truncate_decimals(&self, n: u32) -> Self {
+        let drop = -i64::from(n) - self.exp;
+        if drop <= 0 {
+            return Self::new(self.negative, &self.digits, self.exp);
+        }
+        let keep = (self.digits.len() as i64 - drop).max(0) as usize;
+        Self::new(self.negative, &self.digits[..keep], self.exp + drop)
+    }
+
+    /// Truncate towards zero to `n` significant digits.
+    fn truncate_significant(&self, n: u32) -> Self {
+        let keep = self.digits.len().min(n as usize);
+        let exp = self.exp + (self.digits.len() - keep) as i64;
+        Self::new(self.negative, &self.digits[..keep], exp)
+    }
+}
+
+/// Whether `s` is in canonical normalized form: `-?(0|[1-9][0-9]*)\.([0-9]*[1-9])?`,
+/// without negative zero.
+fn is_canonical(s: &str) -> bool {
+    let body = s.strip_prefix('-').unwrap_or(s);
+    let Some((int_part, frac_part)) = body.split_once('.') else {
+        return false;
+    };
+    let digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+    digits(int_part)
+        && digits(frac_part)
+        && (int_part == "0" || (!int_part.is_empty() && !int_part.starts_with('0')))
+        && !frac_part.ends_with('0')
+        && s != "-0."
+}
+
+fuzz_target!(|input: (u8, u8, &str)| {
+    let (mode, n, data) = input;
+    let n = u32::from(n % 24);
+    for range in fuzzing::find_numbers(data) {
+        let literal = &data[range];
+        let Some(exact) = Decimal::parse(literal) else {
+            continue;
+        };
+        let exponent = literal
+            .find(['e', 'E'])
+            .map_or(0, |e| literal[e + 1..].parse::<i64>().unwrap_or(i64::MAX));
+        if exponent.abs() > MAX_EXPANDED_EXPONENT {
+            assert_eq!(fuzzing::normalize_number(literal), literal);
+            continue;
+        }
+
+        let normalized = fuzzing::normalize_number(literal);
+        assert!(is_canonical(&normalized), "{literal} → {normalized}");
+        assert_eq!(
+            Decimal::parse(&normalized).as_ref(),
+            Some(&exact),
+            "{literal} → {normalized}"
+        );
+
+        let (precision, expected) = match mode % 2 {
+            0 => (Precision::DecimalPlaces(n), exact.truncate_decimals(n)),
+            _ => (
+                Precision::SignificantDigits(n),
+                exact.truncate_significant(n),
+            ),
+        };
+        let rounded = fuzzing::round_number(literal, precision);
+        assert!(
+            is_canonical(&rounded),
+            "{literal} → {rounded} ({precision})"
+        );
+        assert_eq!(
+            Decimal::parse(&rounded),
+            Some(expected),
+            "{literal} → {rounded} ({precision})"
+        );
+    }
+});
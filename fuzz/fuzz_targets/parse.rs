@@ -0,0 +1,41 @@
+//! Fuzz the STEP section parser, with and without limits.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stepreduce::{Entity, Limits, StepFile, fuzzing};
+
+fuzz_target!(|data: &[u8]| {
+    let sections = fuzzing::parse(data, &Limits::default()).unwrap();
+    for line in &sections.data {
+        assert!(!line.contains('\n'));
+        assert_eq!(line.trim(), line);
+    }
+
+    let limits = Limits {
+        max_file_size: None,
+        max_line_length: Some(256),
+        max_entities: Some(64),
+        max_exponent: Some(308),
+    };
+    if let Ok(limited) = fuzzing::parse(data, &limits) {
+        // Limits only ever reject input, they never change the result.
+        assert_eq!(limited, sections);
+        assert!(limited.data.len() <= 64);
+        assert!(limited.data.iter().all(|line| line.len() <= 256));
+    }
+
+    // The model reads back what it writes, both verbatim and canonical.
+    if let Ok(file) = StepFile::parse(data) {
+        let mut output = Vec::new();
+        file.write(&mut output).unwrap();
+        assert_eq!(StepFile::parse(&output).unwrap().entities, file.entities);
+
+        for mut entity in file.entities {
+            entity.records_mut();
+            assert_eq!(entity.to_string().parse::<Entity>().unwrap(), entity);
+        }
+    }
+
+    let _ = stepreduce::validate(data);
+});
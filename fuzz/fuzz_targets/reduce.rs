@@ -0,0 +1,69 @@
+//! Fuzz the whole reduction and check the output's referential integrity.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stepreduce::{
+    DEFAULT_ANNOTATION_ENTITIES, Error, Issue, Limits, NameHandling, Precision, ReduceOptions,
+};
+
+/// Whether `issue` breaks referential integrity or entity parsing.
+fn is_integrity_issue(issue: &Issue) -> bool {
+    !matches!(issue, Issue::MissingHeaderEntity { .. })
+}
+
+fuzz_target!(|input: (u8, &[u8])| {
+    let (flags, data) = input;
+    let options = ReduceOptions {
+        precision: match flags & 0b11 {
+            0 => None,
+            1 => Some(Precision::DecimalPlaces(3)),
+            2 => Some(Precision::SignificantDigits(6)),
+            _ => Some(Precision::Absolute(1e-4)),
+        },
+        use_step_precision: flags & 0b100 != 0,
+        names: match (flags >> 3) & 0b11 {
+            0 => NameHandling::Ignore,
+            1 => NameHandling::Preserve,
+            _ => NameHandling::PreserveNonEmpty,
+        },
+        annotation_entities: if flags & 0b10_0000 != 0 {
+            DEFAULT_ANNOTATION_ENTITIES
+                .iter()
+                .map(|t| t.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        },
+        shorten_reals: flags & 0b100_0000 != 0,
+        quantize: flags & 0b1000_0000 != 0,
+        verify: true,
+        limits: Limits {
+            max_line_length: Some(4096),
+            max_entities: Some(1024),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // Broken input can't be reduced losslessly; it only must not panic.
+    let clean = !stepreduce::validate(data)
+        .issues
+        .iter()
+        .any(is_integrity_issue);
+
+    match stepreduce::reduce(data, &options) {
+        Ok(output) if clean => {
+            let report = stepreduce::validate(&output);
+            let issues: Vec<_> = report
+                .issues
+                .iter()
+                .filter(|i| is_integrity_issue(i))
+                .collect();
+            assert!(issues.is_empty(), "{issues:?}");
+        }
+        Ok(_) | Err(Error::Limit(_)) => {}
+        Err(e) if clean => panic!("{e}"),
+        Err(_) => {}
+    }
+});
@@ -0,0 +1,51 @@
+//! Differential fuzzing of the hand-written reference, name and uncertainty
+//! scanners against the regexes they replaced. The reference and uncertainty
+//! scanners skip string literals, so those are only compared on input
+//! without quotes.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use libfuzzer_sys::fuzz_target;
+use regex::Regex;
+
+/// `#(\d+)`, but with ASCII digits: with others, the ID did not parse anyway.
+static REF_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#([0-9]+)").unwrap());
+
+static NAME_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([A-Z_]+\()'([^']*)'").unwrap());
+
+static UNCERTAINTY_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)UNCERTAINTY_MEASURE_WITH_UNIT\s*\(\s*LENGTH_MEASURE\s*\(\s*([^)]+)\s*\)")
+        .unwrap()
+});
+
+fuzz_target!(|data: &str| {
+    let name = NAME_PATTERN
+        .captures(data)
+        .map(|caps| caps.get(2).unwrap().as_str());
+    assert_eq!(stepreduce::fuzzing::entity_name(data), name, "input: {data:?}");
+
+    if data.contains('\'') {
+        return;
+    }
+    let references: Vec<_> = REF_PATTERN.find_iter(data).map(|m| m.range()).collect();
+    assert_eq!(
+        stepreduce::fuzzing::find_references(data),
+        references,
+        "input: {data:?}"
+    );
+
+    // Unicode case folding would match e.g. `ſ` for `S`.
+    if data.is_ascii() {
+        let uncertainty = UNCERTAINTY_PATTERN
+            .captures(data)
+            .map(|caps| caps.get(1).unwrap().as_str().trim());
+        assert_eq!(
+            stepreduce::fuzzing::uncertainty_value(data).map(str::trim),
+            uncertainty,
+            "input: {data:?}"
+        );
+    }
+});
@@ -0,0 +1,114 @@
+#ifndef STEPREDUCE_H
+#define STEPREDUCE_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of a C API call.
+typedef enum stepreduce_status {
+  // The call succeeded.
+  STEPREDUCE_OK = 0,
+  // A required pointer was null.
+  STEPREDUCE_ERROR_NULL_ARGUMENT = 1,
+  // The output failed the `verify` self-check.
+  STEPREDUCE_ERROR_VERIFY = 2,
+  // The input exceeds a resource limit.
+  STEPREDUCE_ERROR_LIMIT = 3,
+  // The reduction failed for another reason.
+  STEPREDUCE_ERROR_REDUCE = 4,
+  // The library panicked; this is a bug.
+  STEPREDUCE_ERROR_PANIC = 5,
+  // An argument was out of range, such as a too small options `size`.
+  STEPREDUCE_ERROR_INVALID_ARGUMENT = 6,
+} stepreduce_status;
+
+// Options for [`stepreduce_reduce`]. Initialize with
+// [`stepreduce_options_init`], which records the size of the struct as the
+// caller knows it in `size`. Fields added in later versions go at the end,
+// and the library uses their defaults when they lie beyond `size`, so
+// programs built against an older header keep working.
+typedef struct stepreduce_options {
+  // `sizeof(stepreduce_options)` in the caller's header version.
+  size_t size;
+  // Decimal places for numeric comparison, or a negative value for no
+  // rounding.
+  int32_t max_decimals;
+  // Derive precision from the file's declared uncertainty.
+  bool use_step_precision;
+  // Keep styling and property entities that annotate kept entities.
+  bool keep_annotations;
+  // Check that the output is a lossless quotient of the input.
+  bool verify;
+  // Maximum input size in bytes, or 0 for no limit.
+  size_t max_file_size;
+} stepreduce_options;
+
+// A byte buffer owned by the library. Release it with
+// [`stepreduce_buffer_free`].
+typedef struct stepreduce_buffer {
+  uint8_t *data;
+  size_t len;
+} stepreduce_buffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Fill `options` with the defaults (no rounding, no annotations kept, no
+// verification) and set its `size`, which must be
+// `sizeof(stepreduce_options)`:
+//
+// ```c
+// stepreduce_options options;
+// stepreduce_options_init(&options, sizeof options);
+// ```
+//
+// # Safety
+//
+// `options` must be null or valid for writing `size` bytes.
+enum stepreduce_status stepreduce_options_init(struct stepreduce_options *options, size_t size);
+
+// Reduce the STEP file content `input` of `len` bytes into `output`.
+//
+// `options` may be null for the defaults; otherwise it must have been set
+// up with [`stepreduce_options_init`]. On success, `output` owns a new
+// buffer that must be released with [`stepreduce_buffer_free`]; on failure
+// it is set to an empty buffer.
+//
+// # Safety
+//
+// `input` must point to `len` readable bytes (or may be null if `len` is
+// 0), `options` must be null or point to `options->size` readable bytes,
+// and `output` must be valid for writes.
+enum stepreduce_status stepreduce_reduce(const uint8_t *input,
+                                         size_t len,
+                                         const struct stepreduce_options *options,
+                                         struct stepreduce_buffer *output);
+
+// Release a buffer returned by [`stepreduce_reduce`] and reset it to empty.
+// Does nothing for null or empty buffers.
+//
+// # Safety
+//
+// `buffer` must be null or point to a buffer filled by this library that
+// was not freed yet.
+void stepreduce_buffer_free(struct stepreduce_buffer *buffer);
+
+// The message of the last failed call on this thread, or null if there was
+// none. Valid until the next call into the library on this thread.
+const char *stepreduce_last_error(void);
+
+// A static description of a [`stepreduce_status`], or "unknown status" for
+// any other value. Takes an `int` since C callers may pass any value.
+const char *stepreduce_status_string(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* STEPREDUCE_H */
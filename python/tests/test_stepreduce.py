@@ -0,0 +1,54 @@
+"""Tests for the Python bindings. Run after `maturin develop` with
+`python -m unittest discover python/tests`."""
+
+import pathlib
+import tempfile
+import unittest
+
+import stepreduce
+
+VECTOR = (
+    pathlib.Path(__file__).parents[2]
+    / "test-vectors"
+    / "00000000_290a9120f9f249a7a05cfe9c_step_000.step"
+)
+
+
+class ReduceTest(unittest.TestCase):
+    def test_matches_test_vector(self):
+        expected = VECTOR.with_suffix(".step.min").read_bytes()
+        self.assertEqual(stepreduce.reduce(VECTOR.read_bytes()), expected)
+
+    def test_precision(self):
+        data = VECTOR.read_bytes()
+        exact = stepreduce.reduce(data)
+        rounded = stepreduce.reduce(data, max_decimals=3, use_step_precision=True)
+        self.assertLessEqual(len(rounded), len(exact))
+
+    def test_stats(self):
+        output, stats = stepreduce.reduce_with_stats(VECTOR.read_bytes())
+        self.assertIsInstance(output, bytes)
+        self.assertEqual(
+            stats.input_entities,
+            stats.output_entities + stats.merged + stats.removed,
+        )
+        self.assertIn("ReduceStats(", repr(stats))
+
+    def test_reduce_file(self):
+        with tempfile.TemporaryDirectory() as tmp:
+            output = pathlib.Path(tmp) / "out.step"
+            stats = stepreduce.reduce_file(VECTOR, output)
+            self.assertEqual(output.read_bytes(), stepreduce.reduce(VECTOR.read_bytes()))
+            self.assertGreater(stats.merged, 0)
+
+    def test_missing_file(self):
+        with self.assertRaises(FileNotFoundError):
+            stepreduce.reduce_file("/nonexistent.step", "/nonexistent.min")
+
+    def test_error_hierarchy(self):
+        self.assertTrue(issubclass(stepreduce.VerifyError, stepreduce.StepReduceError))
+        self.assertTrue(issubclass(stepreduce.LimitError, stepreduce.StepReduceError))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,223 @@
+//! Serialization of [`ReduceOptions`](crate::ReduceOptions) (`serde` feature).
+//!
+//! A [`Precision`] is written as in `--type-precision`: an integer for
+//! decimal places, `"sig:N"` or `"tol:EPS"`. Attribute precisions are keyed
+//! by `"TYPE[N]"`. Entity types, in these keys as in the type lists, are
+//! uppercased when read, like the command-line options do.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, Visitor},
+};
+
+use crate::Precision;
+
+impl Serialize for Precision {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::DecimalPlaces(n) => serializer.serialize_u32(*n),
+            Self::SignificantDigits(n) => serializer.collect_str(&format_args!("sig:{n}")),
+            Self::Absolute(eps) => serializer.collect_str(&format_args!("tol:{eps}")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Precision {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PrecisionVisitor;
+
+        impl Visitor<'_> for PrecisionVisitor {
+            type Value = Precision;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a number of decimal places, \"sig:N\" or \"tol:EPS\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Precision, E> {
+                u32::try_from(v)
+                    .map(Precision::DecimalPlaces)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Precision, E> {
+                u32::try_from(v)
+                    .map(Precision::DecimalPlaces)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Precision, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PrecisionVisitor)
+    }
+}
+
+/// `serde(deserialize_with)` for the entity type lists of
+/// [`ReduceOptions`](crate::ReduceOptions), such as
+/// [`root_entities`](crate::ReduceOptions::root_entities).
+pub(crate) fn entity_types<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    Ok(Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|entity_type| entity_type.to_ascii_uppercase())
+        .collect())
+}
+
+/// `serde(deserialize_with)` for [`ReduceOptions::type_precision`](crate::ReduceOptions::type_precision).
+pub(crate) fn type_precision<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, Precision>, D::Error> {
+    Ok(HashMap::<String, Precision>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(entity_type, precision)| (entity_type.to_ascii_uppercase(), precision))
+        .collect())
+}
+
+/// `serde(with)` for [`ReduceOptions::attribute_precision`](crate::ReduceOptions::attribute_precision).
+pub(crate) mod attribute_precision {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        map: &HashMap<(String, usize), Precision>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        map.iter()
+            .map(|((entity_type, position), precision)| {
+                (format!("{entity_type}[{position}]"), precision)
+            })
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<(String, usize), Precision>, D::Error> {
+        HashMap::<String, Precision>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, precision)| {
+                let (entity_type, position) = key
+                    .strip_suffix(']')
+                    .and_then(|rest| rest.split_once('['))
+                    .and_then(|(t, n)| Some((t, n.parse().ok()?)))
+                    .ok_or_else(|| {
+                        de::Error::custom(format!("'{key}' is not of the form TYPE[N]"))
+                    })?;
+                Ok(((entity_type.to_ascii_uppercase(), position), precision))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Limits, NameHandling, ReduceOptions};
+
+    use super::*;
+
+    const TOML: &str = r#"
+precision = 6
+type-precision = { DIRECTION = "sig:9" }
+attribute-precision = { "CIRCLE[2]" = "tol:0.0001" }
+names = "preserve-non-empty"
+identity-entities = ["DOCUMENT"]
+root-entities = ["DRAUGHTING_CALLOUT"]
+annotation-entities = ["STYLED_ITEM"]
+shorten-reals = true
+max-rounds = 3
+
+[limits]
+max-file-size = 1000000
+"#;
+
+    fn options() -> ReduceOptions {
+        ReduceOptions {
+            precision: Some(Precision::DecimalPlaces(6)),
+            type_precision: HashMap::from([(
+                "DIRECTION".to_string(),
+                Precision::SignificantDigits(9),
+            )]),
+            attribute_precision: HashMap::from([(
+                ("CIRCLE".to_string(), 2),
+                Precision::Absolute(1e-4),
+            )]),
+            names: NameHandling::PreserveNonEmpty,
+            identity_entities: vec!["DOCUMENT".to_string()],
+            root_entities: vec!["DRAUGHTING_CALLOUT".to_string()],
+            annotation_entities: vec!["STYLED_ITEM".to_string()],
+            shorten_reals: true,
+            max_rounds: Some(3),
+            limits: Limits {
+                max_file_size: Some(1_000_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    mod deserialize {
+        use super::*;
+
+        #[test]
+        fn all_fields() {
+            assert_eq!(toml::from_str::<ReduceOptions>(TOML).unwrap(), options());
+            assert_eq!(
+                toml::from_str::<ReduceOptions>("").unwrap(),
+                ReduceOptions::default()
+            );
+        }
+
+        #[test]
+        fn uppercases_types() {
+            let toml = r#"
+type-precision = { direction = "sig:9" }
+attribute-precision = { "Circle[2]" = "tol:0.0001" }
+identity-entities = ["document"]
+root-entities = ["Draughting_Callout"]
+annotation-entities = ["styled_item"]
+"#;
+            let parsed = toml::from_str::<ReduceOptions>(toml).unwrap();
+            let expected = options();
+            assert_eq!(parsed.type_precision, expected.type_precision);
+            assert_eq!(parsed.attribute_precision, expected.attribute_precision);
+            assert_eq!(parsed.identity_entities, expected.identity_entities);
+            assert_eq!(parsed.root_entities, expected.root_entities);
+            assert_eq!(parsed.annotation_entities, expected.annotation_entities);
+        }
+
+        #[test]
+        fn errors() {
+            for (toml, message) in [
+                ("precision = -1", "invalid value"),
+                ("precision = \"sig:x\"", "invalid precision"),
+                (
+                    "type-precision = { DIRECTION = \"sig:0\" }",
+                    "invalid precision",
+                ),
+                ("attribute-precision = { CIRCLE = 2 }", "TYPE[N]"),
+                ("names = \"keep\"", "unknown variant"),
+                ("verfiy = true", "unknown field"),
+            ] {
+                let err = toml::from_str::<ReduceOptions>(toml).unwrap_err();
+                assert!(err.to_string().contains(message), "{toml}: {err}");
+            }
+        }
+    }
+
+    mod serialize {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let toml = toml::to_string(&options()).unwrap();
+            assert_eq!(toml::from_str::<ReduceOptions>(&toml).unwrap(), options());
+        }
+    }
+}
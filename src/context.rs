@@ -0,0 +1,334 @@
+//! Representation contexts: their length units and uncertainties, and which
+//! entities belong to which context.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    deduplicate::complex_partials, normalize::extract_uncertainty, parse::Instance,
+    references::collect_references, verify::entity_table,
+};
+
+/// Maximum depth of unit conversion chains followed.
+const MAX_UNIT_DEPTH: usize = 8;
+
+/// Significant decimal places per entity, derived from the uncertainty of
+/// the representation context each entity belongs to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ContextDecimals {
+    /// Decimal places by entity ID for entities reachable from a
+    /// representation. Empty if all contexts agree.
+    pub entities: HashMap<u32, u32>,
+    /// Decimal places for all other entities: the finest of all contexts.
+    pub fallback: Option<u32>,
+}
+
+impl ContextDecimals {
+    /// The decimal places for entity `id`.
+    pub fn get(&self, id: u32) -> Option<u32> {
+        self.entities.get(&id).copied().or(self.fallback)
+    }
+}
+
+/// The significant decimal places of an uncertainty: `ceil(-log10(value)) + 1`.
+///
+/// The exponent is snapped to 9 decimals first so that unit conversions like
+/// `0.0254 mm → 0.001 in` don't gain a digit through float error.
+fn uncertainty_decimals(value: f64) -> u32 {
+    let exponent = ((-value.log10()) * 1e9).round() / 1e9;
+    exponent.ceil() as u32 + 1
+}
+
+/// The partials of `rhs`: all of them for a complex instance, or `rhs`
+/// itself.
+fn partials(rhs: &str) -> Vec<&str> {
+    complex_partials(rhs).unwrap_or_else(|| {
+        let trimmed = rhs.trim();
+        vec![trimmed.strip_suffix(';').unwrap_or(trimmed).trim_end()]
+    })
+}
+
+/// Split the argument list of `TYPE( a, (b, c), 'd,e' )` into its top-level
+/// arguments.
+fn split_args(partial: &str) -> Option<(&str, Vec<&str>)> {
+    let open = partial.find('(')?;
+    let close = partial.rfind(')')?;
+    let entity_type = partial[..open].trim();
+    let inner = partial.get(open + 1..close)?;
+
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, b) in inner.bytes().enumerate() {
+        match b {
+            b'\'' => in_string = !in_string,
+            b'(' if !in_string => depth += 1,
+            b')' if !in_string => depth -= 1,
+            b',' if !in_string && depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() || !args.is_empty() {
+        args.push(last);
+    }
+    Some((entity_type, args))
+}
+
+/// The arguments of the partial of type `entity_type` in `rhs`.
+fn find_partial<'a>(rhs: &'a str, entity_type: &str) -> Option<Vec<&'a str>> {
+    partials(rhs)
+        .into_iter()
+        .filter_map(split_args)
+        .find(|(t, _)| *t == entity_type)
+        .map(|(_, args)| args)
+}
+
+/// Parse an entity reference argument like `#12`.
+fn reference(arg: &str) -> Option<u32> {
+    arg.strip_prefix('#')?.trim().parse().ok()
+}
+
+/// Parse a measure argument like `LENGTH_MEASURE( 1.E-07 )` or `25.4`.
+fn measure_value(arg: &str) -> Option<f64> {
+    let value = match (arg.find('('), arg.rfind(')')) {
+        (Some(open), Some(close)) => &arg[open + 1..close],
+        _ => arg,
+    };
+    value.trim().parse().ok()
+}
+
+/// The size of length unit `id` in metres, following conversion-based units.
+fn length_scale(table: &HashMap<u32, &str>, id: u32, depth: usize) -> Option<f64> {
+    let rhs = table.get(&id)?;
+    if let Some(args) = find_partial(rhs, "SI_UNIT") {
+        if args.get(1) != Some(&".METRE.") {
+            return None;
+        }
+        return Some(match args.first().copied()? {
+            "$" => 1.0,
+            ".EXA." => 1e18,
+            ".PETA." => 1e15,
+            ".TERA." => 1e12,
+            ".GIGA." => 1e9,
+            ".MEGA." => 1e6,
+            ".KILO." => 1e3,
+            ".HECTO." => 1e2,
+            ".DECA." => 1e1,
+            ".DECI." => 1e-1,
+            ".CENTI." => 1e-2,
+            ".MILLI." => 1e-3,
+            ".MICRO." => 1e-6,
+            ".NANO." => 1e-9,
+            ".PICO." => 1e-12,
+            ".FEMTO." => 1e-15,
+            ".ATTO." => 1e-18,
+            _ => return None,
+        });
+    }
+    if depth < MAX_UNIT_DEPTH
+        && let Some(args) = find_partial(rhs, "CONVERSION_BASED_UNIT")
+    {
+        let factor = table.get(&reference(args.get(1)?)?)?;
+        let (_, args) = partials(factor)
+            .into_iter()
+            .filter_map(split_args)
+            .find(|(t, _)| t.ends_with("MEASURE_WITH_UNIT"))?;
+        let value = measure_value(args.first()?)?;
+        return Some(value * length_scale(table, reference(args.get(1)?)?, depth + 1)?);
+    }
+    None
+}
+
+/// Whether unit `rhs` measures length.
+fn is_length_unit(rhs: &str) -> bool {
+    find_partial(rhs, "LENGTH_UNIT").is_some()
+        || find_partial(rhs, "SI_UNIT").is_some_and(|args| args.get(1) == Some(&".METRE."))
+}
+
+/// The decimal places of context `rhs`: the finest of its length
+/// uncertainties, converted to the context's length unit.
+fn context_decimals_of(table: &HashMap<u32, &str>, rhs: &str) -> Option<u32> {
+    let uncertainties = find_partial(rhs, "GLOBAL_UNCERTAINTY_ASSIGNED_CONTEXT")?;
+    let context_unit = find_partial(rhs, "GLOBAL_UNIT_ASSIGNED_CONTEXT").and_then(|args| {
+        collect_references(args.first()?)
+            .into_iter()
+            .find(|id| table.get(id).is_some_and(|unit| is_length_unit(unit)))
+    });
+    let context_scale = context_unit.and_then(|id| length_scale(table, id, 0));
+
+    collect_references(uncertainties.first()?)
+        .into_iter()
+        .filter_map(|id| {
+            let args = find_partial(table.get(&id)?, "UNCERTAINTY_MEASURE_WITH_UNIT")?;
+            let measure = args.first()?;
+            let keyword = measure.get(.."LENGTH_MEASURE".len())?;
+            if !keyword.eq_ignore_ascii_case("LENGTH_MEASURE") {
+                return None;
+            }
+            let mut value = measure_value(measure)?;
+            let unit_scale = reference(args.get(1)?).and_then(|id| length_scale(table, id, 0));
+            if let (Some(unit_scale), Some(context_scale)) = (unit_scale, context_scale) {
+                value *= unit_scale / context_scale;
+            }
+            (value > 0.0).then(|| uncertainty_decimals(value))
+        })
+        .max()
+}
+
+/// Resolve the decimal places of every representation context and assign
+/// each entity the finest context of the representations it belongs to.
+///
+/// A representation is any entity referencing a context; its members are
+/// the entities reachable from it without passing through another
+/// representation or context. If no context declares a length uncertainty,
+/// the first `UNCERTAINTY_MEASURE_WITH_UNIT` in the file is used for all
+/// entities.
+pub(crate) fn context_decimals(data: &[impl Instance]) -> ContextDecimals {
+    let table = entity_table(data);
+
+    let contexts: HashMap<u32, u32> = table
+        .iter()
+        .filter_map(|(&id, rhs)| Some((id, context_decimals_of(&table, rhs)?)))
+        .collect();
+    let Some(&finest) = contexts.values().max() else {
+        return ContextDecimals {
+            entities: HashMap::new(),
+            fallback: extract_uncertainty(data),
+        };
+    };
+    let mut result = ContextDecimals {
+        entities: HashMap::new(),
+        fallback: Some(finest),
+    };
+    if contexts.values().all(|&d| d == finest) {
+        return result;
+    }
+
+    let references: HashMap<u32, Vec<u32>> = table
+        .iter()
+        .map(|(&id, rhs)| (id, collect_references(rhs).into_iter().collect()))
+        .collect();
+    let representations: HashMap<u32, u32> = references
+        .iter()
+        .filter(|(id, _)| !contexts.contains_key(id))
+        .filter_map(|(&id, refs)| {
+            let decimals = refs.iter().filter_map(|r| contexts.get(r)).max()?;
+            Some((id, *decimals))
+        })
+        .collect();
+
+    for (&representation, &decimals) in &representations {
+        let mut stack = vec![representation];
+        let mut seen = HashSet::from([representation]);
+        while let Some(id) = stack.pop() {
+            let entry = result.entities.entry(id).or_insert(decimals);
+            *entry = (*entry).max(decimals);
+            for &r in references.get(&id).into_iter().flatten() {
+                if !contexts.contains_key(&r) && !representations.contains_key(&r) && seen.insert(r)
+                {
+                    stack.push(r);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::lines;
+
+    /// A millimetre context (`#10`) with a 1e-6 mm uncertainty and an inch
+    /// context (`#20`) with a 0.0254 mm (0.001 in) uncertainty, each with
+    /// one shape representation.
+    fn two_contexts() -> Vec<String> {
+        lines(&[
+            "#1=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));",
+            "#2=LENGTH_MEASURE_WITH_UNIT(LENGTH_MEASURE(25.4),#1);",
+            "#3=(CONVERSION_BASED_UNIT('INCH',#2)LENGTH_UNIT()NAMED_UNIT(#4));",
+            "#4=DIMENSIONAL_EXPONENTS(1.,0.,0.,0.,0.,0.,0.);",
+            "#5=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(1.E-06),#1,'distance_accuracy_value','');",
+            "#6=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(0.0254),#1,'distance_accuracy_value','');",
+            "#10=(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNCERTAINTY_ASSIGNED_CONTEXT((#5))GLOBAL_UNIT_ASSIGNED_CONTEXT((#1))REPRESENTATION_CONTEXT('',''));",
+            "#20=(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNCERTAINTY_ASSIGNED_CONTEXT((#6))GLOBAL_UNIT_ASSIGNED_CONTEXT((#3))REPRESENTATION_CONTEXT('',''));",
+            "#11=SHAPE_REPRESENTATION('',(#12),#10);",
+            "#12=CARTESIAN_POINT('',(0.1234567,0.,0.));",
+            "#21=SHAPE_REPRESENTATION('',(#22),#20);",
+            "#22=CARTESIAN_POINT('',(0.1234567,0.,0.));",
+            "#30=SHAPE_REPRESENTATION_RELATIONSHIP('','',#11,#21);",
+        ])
+    }
+
+    mod length_scale {
+        use super::*;
+
+        #[test]
+        fn si_and_conversion_based() {
+            let data = two_contexts();
+            let table = entity_table(&data);
+            assert_eq!(length_scale(&table, 1, 0), Some(1e-3));
+            let inch = length_scale(&table, 3, 0).unwrap();
+            assert!((inch - 0.0254).abs() < 1e-12);
+            assert_eq!(length_scale(&table, 4, 0), None);
+        }
+
+        #[test]
+        fn cyclic_conversion() {
+            let data = lines(&[
+                "#1=(CONVERSION_BASED_UNIT('X',#2)LENGTH_UNIT()NAMED_UNIT(*));",
+                "#2=LENGTH_MEASURE_WITH_UNIT(LENGTH_MEASURE(2.),#1);",
+            ]);
+            assert_eq!(length_scale(&entity_table(&data), 1, 0), None);
+        }
+    }
+
+    mod context_decimals {
+        use super::*;
+
+        #[test]
+        fn per_representation() {
+            let decimals = context_decimals(&two_contexts());
+            assert_eq!(decimals.fallback, Some(7));
+            assert_eq!(decimals.get(12), Some(7));
+            assert_eq!(decimals.get(22), Some(4));
+            assert_eq!(decimals.get(21), Some(4));
+            // Entities outside any representation use the finest context.
+            assert_eq!(decimals.get(30), Some(7));
+        }
+
+        #[test]
+        fn agreeing_contexts() {
+            let mut data = two_contexts();
+            data[5] =
+                "#6=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(2.54E-05),#1,'','');".to_string();
+            let decimals = context_decimals(&data);
+            assert_eq!(decimals.fallback, Some(7));
+            assert!(decimals.entities.is_empty());
+        }
+
+        #[test]
+        fn lowercase_measure() {
+            let mut data = two_contexts();
+            data[4] =
+                "#5=UNCERTAINTY_MEASURE_WITH_UNIT(length_measure(1.E-06),#1,'','');".to_string();
+            assert_eq!(context_decimals(&data).get(12), Some(7));
+        }
+
+        #[test]
+        fn no_context() {
+            let data = lines(&[
+                "#5=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(1.E-03),#1,'','');",
+                "#12=CARTESIAN_POINT('',(0.1234567,0.,0.));",
+            ]);
+            let decimals = context_decimals(&data);
+            assert_eq!(decimals.fallback, Some(4));
+            assert_eq!(decimals.get(12), Some(4));
+        }
+    }
+}
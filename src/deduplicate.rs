@@ -1,8 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    str::FromStr,
+};
 
 use crate::{
-    normalize::{normalize_entity_name, normalize_numbers_in_line},
-    references::remap_references,
+    normalize::{PrecisionPolicy, entity_name, normalize_entity_name, normalize_numbers_in_line},
+    parse::Instance,
+    progress::{Cancelled, Monitor, Phase},
 };
 
 /// Extract the entity type name from a right-hand side string.
@@ -24,9 +29,106 @@ pub(crate) fn get_entity_type(rhs: &str) -> &str {
     }
 }
 
+/// Split a complex entity instance like `( A(…) B(…) C() )` into its partial
+/// entity instances (`A(…)`, `B(…)`, `C()`), in file order.
+///
+/// Returns `None` if `rhs` is not a well-formed complex instance. A trailing
+/// `;` is ignored.
+pub(crate) fn complex_partials(rhs: &str) -> Option<Vec<&str>> {
+    let trimmed = rhs.trim();
+    let trimmed = trimmed.strip_suffix(';').unwrap_or(trimmed).trim_end();
+    let inner = trimmed.strip_prefix('(')?.strip_suffix(')')?;
+    let bytes = inner.as_bytes();
+
+    let mut partials = Vec::new();
+    let mut pos = 0;
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos == bytes.len() {
+            break;
+        }
+
+        let start = pos;
+        while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+            pos += 1;
+        }
+        if pos == start {
+            return None;
+        }
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&b'(') {
+            return None;
+        }
+
+        // Find the matching closing parenthesis, skipping string literals.
+        let mut depth = 0;
+        let mut in_string = false;
+        loop {
+            match bytes.get(pos)? {
+                b'\'' => in_string = !in_string,
+                b'(' if !in_string => depth += 1,
+                b')' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        pos += 1;
+        partials.push(&inner[start..pos]);
+    }
+
+    (!partials.is_empty()).then_some(partials)
+}
+
+/// Extract all entity type names of an instance.
+///
+/// For a simple instance this is the single type from [`get_entity_type`].
+/// For a complex instance it is the type of every partial instance, in file
+/// order.
+pub(crate) fn get_entity_types(rhs: &str) -> Vec<&str> {
+    match complex_partials(rhs) {
+        Some(partials) => partials.iter().map(|p| get_entity_type(p)).collect(),
+        None => vec![get_entity_type(rhs)],
+    }
+}
+
+/// A human-readable type label: the type name for simple instances, and the
+/// sorted partial types in parentheses (e.g. `(LENGTH_UNIT NAMED_UNIT
+/// SI_UNIT)`) for complex ones.
+pub(crate) fn entity_type_label(rhs: &str) -> String {
+    let mut types = get_entity_types(rhs);
+    if types.len() == 1 {
+        return types[0].to_string();
+    }
+    types.sort_unstable();
+    format!("({})", types.join(" "))
+}
+
+/// Rewrite a complex instance with its partial instances in the sorted order
+/// required by ISO 10303-21, so that equal instances written in a different
+/// order compare equal. Returns `None` for simple instances.
+fn canonicalize_complex(rhs: &str) -> Option<String> {
+    let mut partials = complex_partials(rhs)?;
+    partials.sort_by_key(|p| get_entity_type(p));
+    let semicolon = if rhs.trim_end().ends_with(';') {
+        ";"
+    } else {
+        ""
+    };
+    Some(format!("({}){semicolon}", partials.join(" ")))
+}
+
 /// STEP entity types that carry identity and must never be deduplicated, even
 /// if their normalized content is identical.
-const IDENTITY_ENTITIES: &[&str] = &[
+pub const IDENTITY_ENTITIES: &[&str] = &[
     "PRODUCT",
     "PRODUCT_DEFINITION",
     "PRODUCT_DEFINITION_FORMATION",
@@ -52,79 +154,236 @@ const IDENTITY_ENTITIES: &[&str] = &[
     "DESIGN_CONTEXT",
 ];
 
-fn is_identity_entity(entity_type: &str) -> bool {
-    IDENTITY_ENTITIES.contains(&entity_type)
+/// Return `true` if any of the (partial) types carries identity, being in
+/// [`IDENTITY_ENTITIES`] or `extra`.
+fn is_identity_entity(entity_types: &[&str], extra: &[String]) -> bool {
+    entity_types
+        .iter()
+        .any(|t| IDENTITY_ENTITIES.contains(t) || extra.iter().any(|e| e == t))
 }
 
-/// Iteratively deduplicate STEP data lines.
+/// Build the key under which two right-hand sides are considered equal.
 ///
-/// Entities with identical normalized right-hand sides are merged (the
-/// duplicate is removed and all references to it are remapped to the
-/// surviving entity). Identity-bearing entities are always kept separate.
-///
-/// The loop repeats until a fixed point is reached (no further merges).
-pub(crate) fn deduplicate(data_lines: &[String], max_decimals: Option<u32>) -> Vec<String> {
-    let mut out_lines: Vec<String> = data_lines.to_vec();
+/// Complex instances are canonicalized to sorted partial order, numbers are
+/// normalized (and optionally rounded to the precision of original entity
+/// `id`) and the entity's quoted name is blanked. The original text is never
+/// modified.
+pub(crate) fn comparison_key(rhs: &str, precision: &PrecisionPolicy, id: u32) -> String {
+    let canonical = canonicalize_complex(rhs);
+    let rhs = canonical.as_deref().unwrap_or(rhs);
+    let norm_rhs = normalize_numbers_in_line(rhs, precision, id);
+    normalize_entity_name(&norm_rhs)
+}
 
-    loop {
-        let in_lines = out_lines;
-        let n = in_lines.len();
-        let mut uniques: HashMap<String, u32> = HashMap::with_capacity(n);
-        let mut lookup: HashMap<u32, u32> = HashMap::with_capacity(n);
-        out_lines = Vec::with_capacity(n);
-
-        for line in &in_lines {
-            let Some(eq) = line.find('=') else {
-                continue;
-            };
+/// How entity names (the leading quoted string attribute, e.g. the `'bolt'`
+/// in `PRODUCT_DEFINITION('bolt',…)`) are treated during deduplication.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum NameHandling {
+    /// Names are ignored: entities differing only in their name are merged
+    /// and the surviving entity's name is kept.
+    #[default]
+    Ignore,
+    /// Names are part of the comparison: only entities with identical names
+    /// are merged.
+    Preserve,
+    /// Like [`Preserve`](Self::Preserve), but an entity with an empty name
+    /// may also be merged into an otherwise equal entity with a non-empty
+    /// name, since no label is lost that way.
+    PreserveNonEmpty,
+}
 
-            let old_num: u32 = line[1..eq].trim().parse().unwrap_or(0);
-            let rhs = line[eq + 1..].trim();
+impl FromStr for NameHandling {
+    type Err = String;
 
-            let entity_type = get_entity_type(rhs);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Self::Ignore),
+            "preserve" => Ok(Self::Preserve),
+            "preserve-non-empty" => Ok(Self::PreserveNonEmpty),
+            _ => Err(format!(
+                "unknown name handling '{s}' (expected ignore, preserve or preserve-non-empty)"
+            )),
+        }
+    }
+}
 
-            // Normalize a copy for comparison; keep original for output.
-            let mut norm_rhs = normalize_numbers_in_line(rhs, max_decimals);
-            norm_rhs = normalize_entity_name(&norm_rhs);
+impl fmt::Display for NameHandling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ignore => "ignore",
+            Self::Preserve => "preserve",
+            Self::PreserveNonEmpty => "preserve-non-empty",
+        })
+    }
+}
 
-            if is_identity_entity(entity_type) {
-                // Force uniqueness for identity-bearing entities.
-                while uniques.contains_key(&norm_rhs) {
-                    norm_rhs.push(' ');
-                }
-                let new_id = out_lines.len() as u32 + 1;
-                uniques.insert(norm_rhs, new_id);
-                lookup.insert(old_num, new_id);
-                out_lines.push(format!("#{new_id}={rhs}"));
-            } else if let Some(&existing_id) = uniques.get(&norm_rhs) {
-                lookup.insert(old_num, existing_id);
-            } else {
-                let new_id = out_lines.len() as u32 + 1;
-                uniques.insert(norm_rhs, new_id);
-                lookup.insert(old_num, new_id);
-                out_lines.push(format!("#{new_id}={rhs}"));
-            }
+impl NameHandling {
+    /// Whether an entity named `merged` may be merged into one named
+    /// `survivor` without losing a label.
+    pub(crate) fn allows_merge(self, merged: Option<&str>, survivor: Option<&str>) -> bool {
+        match self {
+            Self::Ignore => true,
+            Self::Preserve => merged == survivor,
+            Self::PreserveNonEmpty => merged.is_none_or(str::is_empty) || merged == survivor,
         }
+    }
+}
+
+/// Record of one entity being merged into another during deduplication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Merge {
+    /// The round of the pipeline (starting at 1) in which the merge happened.
+    pub round: usize,
+    /// The pass of [`Deduplicate`](crate::Deduplicate) within the round
+    /// (starting at 1) in which the merge happened.
+    pub pass: usize,
+    /// Original ID of the surviving entity.
+    pub into: u32,
+    /// The normalized key under which both entities compared equal.
+    pub key: String,
+}
+
+/// Detailed record of the merges of a reduction, for explaining them.
+#[derive(Debug, Default)]
+pub(crate) struct DedupTrace {
+    /// The round of the pipeline that the next merges happen in.
+    pub round: usize,
+    /// Merges keyed by the original ID of the entity that was merged away.
+    pub merges: HashMap<u32, Merge>,
+}
 
-        // Remap all references.
-        for line in &mut out_lines {
-            let eq = line.find('=').unwrap();
-            let lhs = &line[..eq];
-            let rhs = &line[eq + 1..];
-            *line = format!("{lhs}={}", remap_references(rhs, &lookup));
+/// The outcome of one [`deduplicate_pass`].
+#[derive(Debug)]
+pub(crate) struct DedupPass {
+    /// Map from every input entity ID to its new ID. The kept entities are
+    /// numbered from 1 in input order.
+    pub lookup: HashMap<u32, u32>,
+    /// The input index of each kept entity (index = new ID − 1).
+    pub kept: Vec<usize>,
+    /// The original ID of each kept entity (index = new ID − 1).
+    pub origins: Vec<u32>,
+    /// Number of merged entities whose non-empty name differed from the
+    /// surviving entity's name. Always 0 unless names are ignored.
+    pub labels_lost: usize,
+}
+
+/// Deduplicate STEP data lines once: merge the entities of `lines` with
+/// equal comparison keys into the first one, without remapping references.
+/// Identity-bearing entities are always kept separate.
+///
+/// Entity names take part in the comparison as selected by `names`, and
+/// the types in `identity` carry identity in addition to
+/// [`IDENTITY_ENTITIES`]. `origin` maps each ID of `lines` to the original
+/// ID that selects its precision (and identifies it in `trace`), and `pass`
+/// numbers the pass for progress reports and `trace`.
+///
+/// Merged entities can make their referrers equal, so
+/// [`Deduplicate`](crate::Deduplicate) repeats this until nothing is merged.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn deduplicate_pass(
+    lines: &[impl Instance],
+    precision: &PrecisionPolicy,
+    names: NameHandling,
+    identity: &[String],
+    origin: &dyn Fn(u32) -> u32,
+    pass: usize,
+    monitor: &Monitor,
+    mut trace: Option<&mut DedupTrace>,
+) -> Result<DedupPass, Cancelled> {
+    let n = lines.len();
+    let mut uniques: HashMap<String, u32> = HashMap::with_capacity(n);
+    let mut result = DedupPass {
+        lookup: HashMap::with_capacity(n),
+        kept: Vec::with_capacity(n),
+        origins: Vec::with_capacity(n),
+        labels_lost: 0,
+    };
+    let mut kept_names: Vec<Option<&str>> = Vec::with_capacity(n);
+    let phase = Phase::Deduplicate { pass };
+
+    for (i, line) in lines.iter().enumerate() {
+        monitor.step(phase, i, n)?;
+        let Some((old_num, rhs)) = line.instance() else {
+            continue;
+        };
+        let rhs = rhs.trim();
+
+        let entity_types = get_entity_types(rhs);
+        let origin = origin(old_num);
+
+        // Normalize a copy for comparison; keep original for output.
+        let mut norm_rhs = comparison_key(rhs, precision, origin);
+        let key_len = norm_rhs.len();
+        if !precision.entities.is_empty() {
+            // Entities rounded to different precisions are never merged.
+            write!(norm_rhs, "\u{1}{:?}", precision.base(origin)).unwrap();
         }
+        let name = entity_name(rhs);
 
-        if in_lines.len() <= out_lines.len() {
-            break;
+        // Entities whose name must be preserved are only merged into
+        // entities with the same name. All others use the name-less key,
+        // which maps to the first entity with that key whatever its name.
+        let named_key = match (names, name) {
+            (NameHandling::Ignore, _) | (_, None) => None,
+            (NameHandling::PreserveNonEmpty, Some("")) => None,
+            (_, Some(name)) => Some(format!("{norm_rhs}\0{name}")),
+        };
+
+        if is_identity_entity(&entity_types, identity) {
+            // Force uniqueness for identity-bearing entities.
+            while uniques.contains_key(&norm_rhs) {
+                norm_rhs.push(' ');
+            }
+            let new_id = result.kept.len() as u32 + 1;
+            uniques.insert(norm_rhs, new_id);
+            result.lookup.insert(old_num, new_id);
+            result.kept.push(i);
+            result.origins.push(origin);
+            kept_names.push(name);
+        } else if let Some(&existing_id) = uniques.get(named_key.as_ref().unwrap_or(&norm_rhs)) {
+            result.lookup.insert(old_num, existing_id);
+            if name.is_some_and(|n| !n.is_empty()) && name != kept_names[existing_id as usize - 1] {
+                result.labels_lost += 1;
+            }
+            if let Some(t) = trace.as_deref_mut() {
+                let into = result.origins[existing_id as usize - 1];
+                norm_rhs.truncate(key_len);
+                t.merges.insert(
+                    origin,
+                    Merge {
+                        round: t.round,
+                        pass,
+                        into,
+                        key: norm_rhs,
+                    },
+                );
+            }
+        } else {
+            let new_id = result.kept.len() as u32 + 1;
+            if let Some(key) = named_key {
+                uniques.insert(key, new_id);
+            }
+            uniques.entry(norm_rhs).or_insert(new_id);
+            result.lookup.insert(old_num, new_id);
+            result.kept.push(i);
+            result.origins.push(origin);
+            kept_names.push(name);
         }
     }
 
-    out_lines
+    monitor.report(phase, 1.0);
+    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils;
 
     mod get_entity_type {
         use super::*;
@@ -160,32 +419,218 @@ mod tests {
         }
     }
 
-    mod deduplicate {
+    mod get_entity_types {
+        use super::*;
+
         #[test]
-        fn removes_duplicates() {
-            let lines = vec![
-                "#1=CARTESIAN_POINT('',0.,0.,0.)".to_string(),
-                "#2=CARTESIAN_POINT('',0.,0.,0.)".to_string(),
-                "#3=DIRECTION('',1.,0.,0.)".to_string(),
-                "#4=AXIS2_PLACEMENT_3D('',#1,#3,#3)".to_string(),
-                "#5=AXIS2_PLACEMENT_3D('',#2,#3,#3)".to_string(),
+        fn simple() {
+            assert_eq!(get_entity_types("PRODUCT('foo',#1);"), ["PRODUCT"]);
+        }
+
+        #[test]
+        fn complex_entity() {
+            assert_eq!(
+                get_entity_types(
+                    "( REPRESENTATION_RELATIONSHIP('','',#392,#10) \
+                     REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#6468) \
+                     SHAPE_REPRESENTATION_RELATIONSHIP() );"
+                ),
+                [
+                    "REPRESENTATION_RELATIONSHIP",
+                    "REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION",
+                    "SHAPE_REPRESENTATION_RELATIONSHIP",
+                ],
+            );
+        }
+
+        #[test]
+        fn parens_in_strings() {
+            assert_eq!(get_entity_types("( A('(') B(')',(1.,2.)) )"), ["A", "B"],);
+        }
+
+        #[test]
+        fn label() {
+            assert_eq!(
+                entity_type_label("( NAMED_UNIT(*) LENGTH_UNIT() SI_UNIT(.MILLI.,.METRE.) )"),
+                "(LENGTH_UNIT NAMED_UNIT SI_UNIT)",
+            );
+        }
+    }
+
+    mod comparison_key {
+        use super::*;
+
+        #[test]
+        fn complex_order_is_canonical() {
+            assert_eq!(
+                comparison_key(
+                    "( NAMED_UNIT(*) LENGTH_UNIT() SI_UNIT(.MILLI.,.METRE.) );",
+                    &PrecisionPolicy::default(),
+                    1
+                ),
+                comparison_key(
+                    "(LENGTH_UNIT() NAMED_UNIT(*) SI_UNIT(.MILLI.,.METRE.));",
+                    &PrecisionPolicy::default(),
+                    1
+                ),
+            );
+        }
+    }
+
+    mod deduplicate_pass {
+        use super::*;
+
+        const NAMED: [&str; 4] = [
+            "#1=CARTESIAN_POINT('',0.,0.,0.)",
+            "#2=CARTESIAN_POINT('origin',0.,0.,0.)",
+            "#3=CARTESIAN_POINT('',0.,0.,0.)",
+            "#4=CARTESIAN_POINT('tip',0.,0.,0.)",
+        ];
+
+        fn dedup(lines: &[&str], names: NameHandling, identity: &[String]) -> DedupPass {
+            deduplicate_pass(
+                &test_utils::lines(lines),
+                &PrecisionPolicy::default(),
+                names,
+                identity,
+                &|id| id,
+                1,
+                &Monitor::default(),
+                None,
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn skips_non_instances() {
+            let lines = ["/* #1=FOO() */", "=", "#2=CARTESIAN_POINT('',0.,0.,0.)"];
+            let result = dedup(&lines, NameHandling::Ignore, &[]);
+            assert_eq!(result.kept, [2]);
+            assert_eq!(result.lookup, HashMap::from([(2, 1)]));
+        }
+
+        #[test]
+        fn ignores_names() {
+            let result = dedup(&NAMED, NameHandling::Ignore, &[]);
+            assert_eq!(result.kept, [0]);
+            assert_eq!(result.labels_lost, 2);
+        }
+
+        #[test]
+        fn preserves_names() {
+            let result = dedup(&NAMED, NameHandling::Preserve, &[]);
+            assert_eq!(result.kept.len(), 3);
+            assert_eq!(result.lookup[&3], 1);
+            assert_eq!(result.labels_lost, 0);
+        }
+
+        #[test]
+        fn preserves_non_empty_names() {
+            let lines = [
+                "#1=CARTESIAN_POINT('origin',0.,0.,0.)",
+                "#2=CARTESIAN_POINT('',0.,0.,0.)",
+                "#3=CARTESIAN_POINT('tip',0.,0.,0.)",
+            ];
+            let result = dedup(&lines, NameHandling::PreserveNonEmpty, &[]);
+            assert_eq!(result.kept.len(), 2);
+            assert_eq!(result.lookup[&2], 1);
+            assert_eq!(result.labels_lost, 0);
+
+            // An unnamed entity does not absorb later named ones.
+            let result = dedup(&NAMED, NameHandling::PreserveNonEmpty, &[]);
+            assert_eq!(result.kept.len(), 3);
+            assert_eq!(result.labels_lost, 0);
+        }
+
+        #[test]
+        fn maps_merged_ids() {
+            let lines = [
+                "#10=CARTESIAN_POINT('',0.,0.,0.)",
+                "#20=CARTESIAN_POINT('',0.,0.,0.)",
+                "#30=VERTEX_POINT('',#10)",
+                "#40=VERTEX_POINT('',#20)",
             ];
-            let result = super::deduplicate(&lines, None);
-            // #2 should be merged into #1, and #5 into #4
-            assert!(result.len() < lines.len());
+            let result = dedup(&lines, NameHandling::Ignore, &[]);
+            // The vertices only become equal once references are remapped.
+            assert_eq!(result.kept, [0, 2, 3]);
+            assert_eq!(
+                result.lookup,
+                HashMap::from([(10, 1), (20, 1), (30, 2), (40, 3)])
+            );
+            assert_eq!(result.origins, [10, 30, 40]);
+        }
+
+        #[test]
+        fn traces_merges() {
+            let lines = test_utils::lines(&[
+                "#10=CARTESIAN_POINT('',0.,0.,0.)",
+                "#20=CARTESIAN_POINT('',0.,0.,0.)",
+            ]);
+            let mut trace = DedupTrace {
+                round: 2,
+                ..Default::default()
+            };
+            deduplicate_pass(
+                &lines,
+                &PrecisionPolicy::default(),
+                NameHandling::Ignore,
+                &[],
+                &|id| id + 100,
+                3,
+                &Monitor::default(),
+                Some(&mut trace),
+            )
+            .unwrap();
+            assert_eq!(
+                trace.merges,
+                HashMap::from([(
+                    120,
+                    Merge {
+                        round: 2,
+                        pass: 3,
+                        into: 110,
+                        key: "CARTESIAN_POINT('',0.,0.,0.)".to_string(),
+                    }
+                )])
+            );
         }
 
         #[test]
         fn preserves_identity_entities() {
-            let lines = vec![
-                "#1=PRODUCT('a','a',$,(#3))".to_string(),
-                "#2=PRODUCT('b','b',$,(#3))".to_string(),
-                "#3=PRODUCT_CONTEXT('',#4,'design')".to_string(),
+            let lines = [
+                "#1=PRODUCT('a','a',$,(#3))",
+                "#2=PRODUCT('b','b',$,(#3))",
+                "#3=PRODUCT_CONTEXT('',#4,'design')",
             ];
-            let result = super::deduplicate(&lines, None);
             // Both PRODUCTs should survive (identity entities).
-            let product_count = result.iter().filter(|l| l.contains("PRODUCT(")).count();
-            assert_eq!(product_count, 2);
+            assert_eq!(dedup(&lines, NameHandling::Ignore, &[]).kept, [0, 1, 2]);
+        }
+
+        #[test]
+        fn preserves_complex_identity_entities() {
+            let lines = [
+                "#1=(REPRESENTATION_RELATIONSHIP('','',#3,#3) \
+                 REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#3) \
+                 SHAPE_REPRESENTATION_RELATIONSHIP())",
+                "#2=(REPRESENTATION_RELATIONSHIP('','',#3,#3) \
+                 REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#3) \
+                 SHAPE_REPRESENTATION_RELATIONSHIP())",
+                "#3=SHAPE_REPRESENTATION('',(),#3)",
+            ];
+            assert_eq!(dedup(&lines, NameHandling::Ignore, &[]).kept.len(), 3);
+        }
+
+        #[test]
+        fn preserves_extra_identity_entities() {
+            let lines = [
+                "#1=DOCUMENT('a','',#3,'')",
+                "#2=DOCUMENT('a','',#3,'')",
+                "#3=DOCUMENT_TYPE('')",
+            ];
+            let kept =
+                |identity: &[String]| dedup(&lines, NameHandling::Ignore, identity).kept.len();
+            assert_eq!(kept(&[]), 2);
+            assert_eq!(kept(&["DOCUMENT".to_string()]), 3);
         }
     }
 }
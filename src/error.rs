@@ -0,0 +1,120 @@
+use std::{fmt, io};
+
+use crate::{
+    limits::LimitError, model::ParseError, validate::ValidationReport, verify::VerifyError,
+};
+
+/// Errors that can occur while reducing a STEP file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An entity instance of the input is malformed.
+    Parse(ParseError),
+
+    /// The input exceeds one of the configured
+    /// [`ReduceOptions::limits`](crate::ReduceOptions::limits).
+    Limit(LimitError),
+
+    /// The reduced output failed the self-check enabled by
+    /// [`ReduceOptions::verify`](crate::ReduceOptions::verify).
+    Verify(VerifyError),
+
+    /// The reduced output has integrity issues that the input did not have.
+    /// Only checked in debug builds, with
+    /// [`ReduceOptions::verify`](crate::ReduceOptions::verify).
+    Invalid(ValidationReport),
+
+    /// The reduction was cancelled through
+    /// [`ReduceOptions::cancel`](crate::ReduceOptions::cancel).
+    Cancelled,
+
+    /// The input file could not be opened or memory-mapped.
+    Io(io::Error),
+
+    /// An option of the [`ReduceOptions`](crate::ReduceOptions) has an
+    /// invalid value, such as zero significant digits.
+    InvalidOptions(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => e.fmt(f),
+            Self::Limit(e) => e.fmt(f),
+            Self::Verify(e) => e.fmt(f),
+            Self::Invalid(report) => write!(f, "reduced output is invalid: {report}"),
+            Self::Cancelled => f.write_str("reduction cancelled"),
+            Self::Io(e) => e.fmt(f),
+            Self::InvalidOptions(message) => write!(f, "invalid options: {message}"),
+        }
+    }
+}
+
+/// The message of the wrapped error is already part of the `Display` output,
+/// so `source` skips it and returns the wrapped error's own source.
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(e) => e.source(),
+            Self::Limit(e) => e.source(),
+            Self::Verify(e) => e.source(),
+            Self::Invalid(report) => report.source(),
+            Self::Cancelled | Self::InvalidOptions(_) => None,
+            Self::Io(e) => e.source(),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl From<VerifyError> for Error {
+    fn from(e: VerifyError) -> Self {
+        Self::Verify(e)
+    }
+}
+
+impl From<LimitError> for Error {
+    fn from(e: LimitError) -> Self {
+        Self::Limit(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The messages of `error` and its sources, as printed by e.g. `anyhow`.
+    fn chain(error: &Error) -> Vec<String> {
+        std::iter::successors(Some(error as &dyn std::error::Error), |e| e.source())
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn messages_not_repeated() {
+        let parse = Error::from(ParseError {
+            line: 3,
+            message: "expected `;`".to_string(),
+        });
+        assert_eq!(chain(&parse), ["line 3: expected `;`"]);
+
+        let limit = Error::from(LimitError::FileSize {
+            size: 20,
+            limit: 10,
+        });
+        assert_eq!(chain(&limit).len(), 1);
+
+        let io = Error::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(chain(&io).len(), 1);
+    }
+}
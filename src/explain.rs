@@ -0,0 +1,346 @@
+//! Explanations of what the reduction did to a single entity.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    Error, Pipeline, ReduceOptions, StepFile,
+    deduplicate::{entity_type_label, get_entity_types},
+    orphans::{is_gc_root, reachable_from_roots},
+    pipeline::{Trace, instances},
+};
+
+/// One step of a merge chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeStep {
+    /// Round of deduplication and orphan removal (starting at 1) in which
+    /// the merge happened, as counted by
+    /// [`ReduceStats::rounds`](crate::ReduceStats::rounds).
+    pub round: usize,
+    /// Deduplication pass within the round (starting at 1) in which the
+    /// merge happened.
+    pub pass: usize,
+    /// Original ID of the entity that was merged away.
+    pub from: u32,
+    /// Original ID of the entity it was merged into.
+    pub into: u32,
+    /// The normalized key under which both entities compared equal.
+    pub key: String,
+}
+
+/// An entity on the reference path from a GC root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStep {
+    /// Original entity ID.
+    pub id: u32,
+    /// Entity type.
+    pub entity_type: String,
+}
+
+/// What finally happened to the entity (or the entity it was merged into).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// No entity with the requested ID exists in the input.
+    NotFound,
+    /// The entity was removed because no GC root references it.
+    Removed,
+    /// The entity is part of the output.
+    Kept {
+        /// ID of the entity in the reduced output.
+        output_id: u32,
+        /// Reference path from a GC root (or a kept annotation entity) down
+        /// to the entity. Empty if the file has no GC roots, in which case
+        /// nothing is removed.
+        root_path: Vec<PathStep>,
+        /// Whether `root_path` starts at an annotation entity kept because it
+        /// annotates live data, rather than at a GC root.
+        annotation: bool,
+    },
+}
+
+/// Why an entity was kept, merged or removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The requested (original) entity ID.
+    pub id: u32,
+    /// The entity type, or `None` if the entity does not exist.
+    pub entity_type: Option<String>,
+    /// The chain of merges starting at this entity; empty if it survived
+    /// deduplication.
+    pub merges: Vec<MergeStep>,
+    /// The outcome for the last entity in the merge chain.
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(entity_type) = &self.entity_type else {
+            return write!(f, "#{}: no such entity", self.id);
+        };
+        writeln!(f, "#{} ({entity_type})", self.id)?;
+        for step in &self.merges {
+            writeln!(
+                f,
+                "  #{} merged into #{} in round {}, pass {} (key: {})",
+                step.from, step.into, step.round, step.pass, step.key
+            )?;
+        }
+        let subject = self.merges.last().map_or(self.id, |step| step.into);
+        match &self.outcome {
+            Outcome::NotFound => Ok(()),
+            Outcome::Removed => write!(
+                f,
+                "  #{subject} removed as orphan (not reachable from any GC root)"
+            ),
+            Outcome::Kept {
+                output_id,
+                root_path,
+                annotation,
+            } => {
+                write!(f, "  #{subject} kept as #{output_id}")?;
+                if root_path.is_empty() {
+                    write!(f, " (no GC roots in file, nothing removed)")?;
+                } else {
+                    let from = if *annotation {
+                        "kept annotation"
+                    } else {
+                        "GC root"
+                    };
+                    write!(f, "\n  path from {from}:")?;
+                    for step in root_path {
+                        write!(f, "\n    #{} {}", step.id, step.entity_type)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Explain the fate of entity `id` of `file` when reduced with `options`,
+/// by running the standard [`Pipeline`] over it.
+pub(crate) fn explain_entity(
+    mut file: StepFile,
+    id: u32,
+    options: &ReduceOptions,
+) -> Result<Explanation, Error> {
+    let Some(entity_type) = (file.entities.iter())
+        .find(|e| e.id() == id)
+        .map(|e| entity_type_label(&e.body()))
+    else {
+        return Ok(Explanation {
+            id,
+            entity_type: None,
+            merges: Vec::new(),
+            outcome: Outcome::NotFound,
+        });
+    };
+    let types: HashMap<u32, String> = (file.entities.iter())
+        .map(|e| (e.id(), entity_type_label(&e.body())))
+        .collect();
+
+    let mut trace = Trace::default();
+    Pipeline::standard(options.clone()).run_file_traced(&mut file, Some(&mut trace))?;
+
+    let mut merges = Vec::new();
+    let mut current = id;
+    while let Some(merge) = trace.merges.get(&current) {
+        merges.push(MergeStep {
+            round: merge.round,
+            pass: merge.pass,
+            from: current,
+            into: merge.into,
+            key: merge.key.clone(),
+        });
+        current = merge.into;
+    }
+
+    let Some(&output_id) = trace.ids.get(&id) else {
+        return Ok(Explanation {
+            id,
+            entity_type: Some(entity_type),
+            merges,
+            outcome: Outcome::Removed,
+        });
+    };
+
+    // Everything in the output is reachable, so the walk over it finds the
+    // path by which orphan removal kept the entity.
+    let (roots, annotations) = (&options.root_entities, &options.annotation_entities);
+    let output = instances(&file.entities);
+    let reachable = reachable_from_roots(&output, roots, annotations);
+    let mut root_path = Vec::new();
+    let mut node = reachable.contains_key(&output_id).then_some(output_id);
+    let mut start = None;
+    while let Some(n) = node {
+        let original = trace.origins[&n];
+        root_path.push(PathStep {
+            id: original,
+            entity_type: types[&original].clone(),
+        });
+        start = Some(n);
+        node = reachable[&n];
+    }
+    root_path.reverse();
+
+    // The path starts at a GC root or at a kept annotation.
+    let annotation = start.is_some_and(|start| {
+        let (_, body) = output.iter().find(|(id, _)| *id == start).unwrap();
+        !is_gc_root(&get_entity_types(body), roots)
+    });
+    Ok(Explanation {
+        id,
+        entity_type: Some(entity_type),
+        merges,
+        outcome: Outcome::Kept {
+            output_id,
+            root_path,
+            annotation,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "\
+DATA;
+#10=APPLICATION_CONTEXT('core');
+#20=PRODUCT_DEFINITION('pd',#10,#40);
+#30=CARTESIAN_POINT('a',0.,0.,0.);
+#40=CARTESIAN_POINT('b',0.,0.,0.);
+#50=DIRECTION('',1.,0.,0.);
+ENDSEC;
+";
+
+    fn explain_in(input: &str, id: u32, options: &ReduceOptions) -> Explanation {
+        explain_entity(StepFile::parse(input.as_bytes()).unwrap(), id, options).unwrap()
+    }
+
+    fn explain(id: u32) -> Explanation {
+        explain_in(INPUT, id, &ReduceOptions::default())
+    }
+
+    #[test]
+    fn merged_and_kept() {
+        let explanation = explain(40);
+        assert_eq!(
+            explanation.merges,
+            [MergeStep {
+                round: 1,
+                pass: 1,
+                from: 40,
+                into: 30,
+                key: "CARTESIAN_POINT('',0.,0.,0.)".to_string(),
+            }]
+        );
+        let Outcome::Kept {
+            output_id,
+            root_path,
+            annotation,
+        } = explanation.outcome
+        else {
+            panic!("expected entity to be kept");
+        };
+        assert_eq!(output_id, 3);
+        let path: Vec<u32> = root_path.iter().map(|step| step.id).collect();
+        assert_eq!(path, [20, 30]);
+        assert!(!annotation);
+    }
+
+    #[test]
+    fn kept_annotation() {
+        let input = "\
+DATA;
+#10=APPLICATION_CONTEXT('core');
+#20=PRODUCT_DEFINITION('pd',#10,#30);
+#30=ADVANCED_FACE('',(),$,.T.);
+#40=STYLED_ITEM('color',(#50),#30);
+#50=PRESENTATION_STYLE_ASSIGNMENT(());
+ENDSEC;
+";
+        let options = ReduceOptions {
+            annotation_entities: vec!["STYLED_ITEM".to_string()],
+            ..Default::default()
+        };
+        let explanation = explain_in(input, 50, &options);
+        let Outcome::Kept {
+            root_path,
+            annotation,
+            ..
+        } = &explanation.outcome
+        else {
+            panic!("expected entity to be kept");
+        };
+        let path: Vec<u32> = root_path.iter().map(|step| step.id).collect();
+        assert_eq!(path, [40, 50]);
+        assert!(annotation);
+        assert!(
+            explanation
+                .to_string()
+                .contains("path from kept annotation:")
+        );
+    }
+
+    #[test]
+    fn shortest_path_from_first_root() {
+        let input = "\
+DATA;
+#10=PRODUCT_DEFINITION('pd',$,#30,$);
+#20=SHAPE_DEFINITION_REPRESENTATION(#40,$);
+#30=SHAPE_REPRESENTATION('',(#50),$);
+#40=SHAPE_REPRESENTATION('',(#50,#60),$);
+#50=CARTESIAN_POINT('',0.,0.,1.);
+#60=CARTESIAN_POINT('',0.,0.,2.);
+#70=PRODUCT_DEFINITION('pd2',$,#60,$);
+ENDSEC;
+";
+        // #50 is as far from #10 as from #20, and #60 closer to #70.
+        for (id, expected) in [(50, [10, 30, 50].as_slice()), (60, &[70, 60])] {
+            let Outcome::Kept { root_path, .. } =
+                explain_in(input, id, &ReduceOptions::default()).outcome
+            else {
+                panic!("expected entity to be kept");
+            };
+            let path: Vec<u32> = root_path.iter().map(|step| step.id).collect();
+            assert_eq!(path, expected);
+        }
+    }
+
+    #[test]
+    fn merge_chain() {
+        let input = "\
+DATA;
+#10=APPLICATION_CONTEXT('core');
+#20=PRODUCT_DEFINITION('pd',#10,#50,#60);
+#30=CARTESIAN_POINT('',0.,0.,0.);
+#40=CARTESIAN_POINT('',0.,0.,0.);
+#50=VERTEX_POINT('',#30);
+#60=VERTEX_POINT('',#40);
+ENDSEC;
+";
+        let explanation = explain_in(input, 60, &ReduceOptions::default());
+        // The vertices are only equal once the points are merged, in the
+        // second pass of the same round.
+        let steps: Vec<_> = (explanation.merges.iter())
+            .map(|step| (step.round, step.pass, step.from, step.into))
+            .collect();
+        assert_eq!(steps, [(1, 2, 60, 50)]);
+        assert!(matches!(
+            explanation.outcome,
+            Outcome::Kept { output_id: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn removed() {
+        let explanation = explain(50);
+        assert!(explanation.merges.is_empty());
+        assert_eq!(explanation.outcome, Outcome::Removed);
+    }
+
+    #[test]
+    fn not_found() {
+        assert_eq!(explain(99).outcome, Outcome::NotFound);
+    }
+}
@@ -0,0 +1,294 @@
+//! C ABI, enabled by the `ffi` feature. The header is `include/stepreduce.h`,
+//! generated with `cbindgen --config cbindgen.toml --output include/stepreduce.h`.
+//!
+//! Failing calls return a [`stepreduce_status`] other than `STEPREDUCE_OK`
+//! and leave a message for [`stepreduce_last_error`].
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{DEFAULT_ANNOTATION_ENTITIES, Error, Precision, ReduceOptions};
+
+/// Result of a C API call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum stepreduce_status {
+    /// The call succeeded.
+    STEPREDUCE_OK = 0,
+    /// A required pointer was null.
+    STEPREDUCE_ERROR_NULL_ARGUMENT = 1,
+    /// The output failed the `verify` self-check.
+    STEPREDUCE_ERROR_VERIFY = 2,
+    /// The input exceeds a resource limit.
+    STEPREDUCE_ERROR_LIMIT = 3,
+    /// The reduction failed for another reason.
+    STEPREDUCE_ERROR_REDUCE = 4,
+    /// The library panicked; this is a bug.
+    STEPREDUCE_ERROR_PANIC = 5,
+    /// An argument was out of range, such as a too small options `size`.
+    STEPREDUCE_ERROR_INVALID_ARGUMENT = 6,
+}
+
+use stepreduce_status::*;
+
+/// Options for [`stepreduce_reduce`]. Initialize with
+/// [`stepreduce_options_init`], which records the size of the struct as the
+/// caller knows it in `size`. Fields added in later versions go at the end,
+/// and the library uses their defaults when they lie beyond `size`, so
+/// programs built against an older header keep working.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct stepreduce_options {
+    /// `sizeof(stepreduce_options)` in the caller's header version.
+    pub size: usize,
+    /// Decimal places for numeric comparison, or a negative value for no
+    /// rounding.
+    pub max_decimals: i32,
+    /// Derive precision from the file's declared uncertainty.
+    pub use_step_precision: bool,
+    /// Keep styling and property entities that annotate kept entities.
+    pub keep_annotations: bool,
+    /// Check that the output is a lossless quotient of the input.
+    pub verify: bool,
+    /// Maximum input size in bytes, or 0 for no limit.
+    pub max_file_size: usize,
+}
+
+/// A byte buffer owned by the library. Release it with
+/// [`stepreduce_buffer_free`].
+#[repr(C)]
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub struct stepreduce_buffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages never contain NUL bytes except from corrupt input lines.
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn fail(status: stepreduce_status, message: String) -> stepreduce_status {
+    set_last_error(message);
+    status
+}
+
+impl From<&stepreduce_options> for ReduceOptions {
+    fn from(options: &stepreduce_options) -> Self {
+        let mut result = ReduceOptions {
+            precision: u32::try_from(options.max_decimals)
+                .ok()
+                .map(Precision::DecimalPlaces),
+            use_step_precision: options.use_step_precision,
+            verify: options.verify,
+            ..Default::default()
+        };
+        if options.keep_annotations {
+            result.annotation_entities = DEFAULT_ANNOTATION_ENTITIES
+                .iter()
+                .map(|t| t.to_string())
+                .collect();
+        }
+        if options.max_file_size > 0 {
+            result.limits.max_file_size = Some(options.max_file_size);
+        }
+        result
+    }
+}
+
+impl Default for stepreduce_options {
+    fn default() -> Self {
+        Self {
+            size: mem::size_of::<Self>(),
+            max_decimals: -1,
+            use_step_precision: false,
+            keep_annotations: false,
+            verify: false,
+            max_file_size: 0,
+        }
+    }
+}
+
+/// Copy the first `size` bytes (at most the whole struct) of `from` over
+/// the defaults and set the `size` field to `size`.
+///
+/// # Safety
+///
+/// `from` must point to `size` readable bytes, or to a whole
+/// `stepreduce_options` if `size` is larger.
+unsafe fn read_options(from: *const stepreduce_options, size: usize) -> stepreduce_options {
+    let mut options = stepreduce_options::default();
+    let len = size.min(mem::size_of::<stepreduce_options>());
+    // SAFETY: the caller guarantees `len` readable bytes, and every field
+    // of the struct is valid for the bytes a C caller stores in it.
+    unsafe { ptr::copy_nonoverlapping(from.cast::<u8>(), (&raw mut options).cast::<u8>(), len) };
+    options.size = size;
+    options
+}
+
+/// Fill `options` with the defaults (no rounding, no annotations kept, no
+/// verification) and set its `size`, which must be
+/// `sizeof(stepreduce_options)`:
+///
+/// ```c
+/// stepreduce_options options;
+/// stepreduce_options_init(&options, sizeof options);
+/// ```
+///
+/// # Safety
+///
+/// `options` must be null or valid for writing `size` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn stepreduce_options_init(
+    options: *mut stepreduce_options,
+    size: usize,
+) -> stepreduce_status {
+    if options.is_null() {
+        return fail(STEPREDUCE_ERROR_NULL_ARGUMENT, "options is null".into());
+    }
+    if size < mem::size_of::<usize>() {
+        return fail(
+            STEPREDUCE_ERROR_INVALID_ARGUMENT,
+            format!("options size {size} is too small"),
+        );
+    }
+    let defaults = stepreduce_options {
+        size,
+        ..Default::default()
+    };
+    let len = size.min(mem::size_of::<stepreduce_options>());
+    // SAFETY: checked for null, the caller guarantees `size` writable bytes.
+    unsafe { ptr::copy_nonoverlapping((&raw const defaults).cast::<u8>(), options.cast(), len) };
+    STEPREDUCE_OK
+}
+
+/// Reduce the STEP file content `input` of `len` bytes into `output`.
+///
+/// `options` may be null for the defaults; otherwise it must have been set
+/// up with [`stepreduce_options_init`]. On success, `output` owns a new
+/// buffer that must be released with [`stepreduce_buffer_free`]; on failure
+/// it is set to an empty buffer.
+///
+/// # Safety
+///
+/// `input` must point to `len` readable bytes (or may be null if `len` is
+/// 0), `options` must be null or point to `options->size` readable bytes,
+/// and `output` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn stepreduce_reduce(
+    input: *const u8,
+    len: usize,
+    options: *const stepreduce_options,
+    output: *mut stepreduce_buffer,
+) -> stepreduce_status {
+    if output.is_null() {
+        return fail(STEPREDUCE_ERROR_NULL_ARGUMENT, "output is null".into());
+    }
+    // SAFETY: checked for null, the caller guarantees validity.
+    let output = unsafe { &mut *output };
+    *output = stepreduce_buffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+    let input: &[u8] = match (input.is_null(), len) {
+        (_, 0) => &[],
+        (true, _) => return fail(STEPREDUCE_ERROR_NULL_ARGUMENT, "input is null".into()),
+        // SAFETY: the caller guarantees `len` readable bytes.
+        (false, _) => unsafe { slice::from_raw_parts(input, len) },
+    };
+    let options = if options.is_null() {
+        ReduceOptions::from(&stepreduce_options::default())
+    } else {
+        // SAFETY: checked for null, the caller guarantees `size` readable
+        // bytes, which include the `size` field itself if it is large enough.
+        let size = unsafe { ptr::read_unaligned(options.cast::<usize>()) };
+        if size < mem::size_of::<usize>() {
+            return fail(
+                STEPREDUCE_ERROR_INVALID_ARGUMENT,
+                format!("options size {size} is too small"),
+            );
+        }
+        // SAFETY: as above.
+        ReduceOptions::from(&unsafe { read_options(options, size) })
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| crate::reduce(input, &options))) {
+        Ok(Ok(reduced)) => {
+            let reduced = reduced.into_boxed_slice();
+            output.len = reduced.len();
+            output.data = Box::into_raw(reduced).cast();
+            STEPREDUCE_OK
+        }
+        Ok(Err(e)) => {
+            let status = match e {
+                Error::Verify(_) => STEPREDUCE_ERROR_VERIFY,
+                Error::Limit(_) => STEPREDUCE_ERROR_LIMIT,
+                Error::InvalidOptions(_) => STEPREDUCE_ERROR_INVALID_ARGUMENT,
+                _ => STEPREDUCE_ERROR_REDUCE,
+            };
+            fail(status, e.to_string())
+        }
+        Err(_) => fail(STEPREDUCE_ERROR_PANIC, "internal error".into()),
+    }
+}
+
+/// Release a buffer returned by [`stepreduce_reduce`] and reset it to empty.
+/// Does nothing for null or empty buffers.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to a buffer filled by this library that
+/// was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn stepreduce_buffer_free(buffer: *mut stepreduce_buffer) {
+    // SAFETY: the caller guarantees validity.
+    let Some(buffer) = (unsafe { buffer.as_mut() }) else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        // SAFETY: `data` and `len` come from `Box::into_raw` of a boxed slice.
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) });
+    }
+    buffer.data = ptr::null_mut();
+    buffer.len = 0;
+}
+
+/// The message of the last failed call on this thread, or null if there was
+/// none. Valid until the next call into the library on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn stepreduce_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// A static description of a [`stepreduce_status`], or "unknown status" for
+/// any other value. Takes an `int` since C callers may pass any value.
+#[unsafe(no_mangle)]
+pub extern "C" fn stepreduce_status_string(status: c_int) -> *const c_char {
+    const DESCRIPTIONS: &[(stepreduce_status, &CStr)] = &[
+        (STEPREDUCE_OK, c"ok"),
+        (STEPREDUCE_ERROR_NULL_ARGUMENT, c"null argument"),
+        (STEPREDUCE_ERROR_VERIFY, c"verification failed"),
+        (STEPREDUCE_ERROR_LIMIT, c"resource limit exceeded"),
+        (STEPREDUCE_ERROR_REDUCE, c"reduction failed"),
+        (STEPREDUCE_ERROR_PANIC, c"internal error"),
+        (STEPREDUCE_ERROR_INVALID_ARGUMENT, c"invalid argument"),
+    ];
+    DESCRIPTIONS
+        .iter()
+        .find(|&&(known, _)| known as c_int == status)
+        .map_or(c"unknown status", |(_, text)| text)
+        .as_ptr()
+}
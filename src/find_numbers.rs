@@ -7,7 +7,7 @@
 //!   3. `-?\.\d+([eE][+-]?\d+)?`       e.g.  .5
 //!
 //! Guard: the character immediately before the match must NOT be [A-Za-z_#] (prevents matching inside
-//! identifiers or entity references).
+//! identifiers or entity references), and must not be the last character of the previous match.
 
 /// A matched float literal inside a string (byte offsets).
 pub(crate) struct NumMatch {
@@ -110,7 +110,10 @@ pub(crate) fn find_numbers(s: &str) -> impl Iterator<Item = NumMatch> + '_ {
 
             if let Some(end) = try_match_number(bytes, pos) {
                 let m = NumMatch { start: pos, end };
-                pos = end;
+                // The regex consumed the predecessor as part of its match, so
+                // the character right after a match can only be the next
+                // match's predecessor, never its start (`.7.7` is one number).
+                pos = end + 1;
                 return Some(m);
             }
 
@@ -119,3 +122,28 @@ pub(crate) fn find_numbers(s: &str) -> impl Iterator<Item = NumMatch> + '_ {
         None
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(s: &str) -> Vec<(usize, usize)> {
+        find_numbers(s).map(|m| (m.start, m.end)).collect()
+    }
+
+    #[test]
+    fn forms() {
+        assert_eq!(ranges("(1.0,-2E+5,.5)"), [(1, 4), (5, 10), (11, 13)]);
+    }
+
+    #[test]
+    fn guard() {
+        assert_eq!(ranges("#12,A1.,_1.,x-1."), [(14, 16)]);
+    }
+
+    #[test]
+    fn adjacent() {
+        assert_eq!(ranges(".7.7"), [(0, 2)]);
+        assert_eq!(ranges("1.-2."), [(0, 2), (3, 5)]);
+    }
+}
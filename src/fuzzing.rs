@@ -0,0 +1,62 @@
+//! Internals exposed to the fuzz targets in `fuzz/`. Not part of the public
+//! API; enabled by the `fuzzing` feature.
+
+use std::ops::Range;
+
+use crate::{Limits, Precision, find_numbers, normalize, parse, references};
+
+/// The byte ranges of all float literals in `s`.
+pub fn find_numbers(s: &str) -> Vec<Range<usize>> {
+    find_numbers::find_numbers(s)
+        .map(|m| m.start..m.end)
+        .collect()
+}
+
+/// The byte ranges of all `#NNN` references in `s` outside of string
+/// literals.
+pub fn find_references(s: &str) -> Vec<Range<usize>> {
+    references::find_references(s).collect()
+}
+
+/// The name of an entity declaration, see `normalize::entity_name`.
+pub fn entity_name(s: &str) -> Option<&str> {
+    normalize::entity_name(s)
+}
+
+/// The value of the first uncertainty declaration in a line, see
+/// `normalize::uncertainty_value`.
+pub fn uncertainty_value(s: &str) -> Option<&str> {
+    normalize::uncertainty_value(s)
+}
+
+/// Normalize a number literal, see `normalize::normalize_number`.
+pub fn normalize_number(s: &str) -> String {
+    normalize::normalize_number(s)
+}
+
+/// Round a number literal, see `normalize::round_number`.
+pub fn round_number(s: &str, precision: Precision) -> String {
+    normalize::round_number(s, precision)
+}
+
+/// The sections of a parsed STEP file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sections {
+    pub header: Vec<String>,
+    pub data: Vec<String>,
+    pub footer: Vec<String>,
+}
+
+/// Split a STEP file into its header, data and footer lines.
+pub fn parse(input: &[u8], limits: &Limits) -> Result<Sections, crate::LimitError> {
+    let parsed = parse::split_sections(input, limits)?;
+    Ok(Sections {
+        header: parsed.header,
+        data: parsed
+            .data
+            .into_iter()
+            .map(|line| line.into_owned())
+            .collect(),
+        footer: parsed.footer,
+    })
+}
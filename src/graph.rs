@@ -0,0 +1,337 @@
+//! Export of the entity reference graph for debugging.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use crate::{
+    Limits,
+    deduplicate::{entity_type_label, get_entity_types},
+    orphans::is_gc_root,
+    parse::Instance,
+    references::collect_references,
+};
+
+/// Output format for [`export_graph`](crate::export_graph).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    #[default]
+    Dot,
+    /// GraphML (XML).
+    GraphMl,
+    /// JSON with `nodes` and `edges` arrays.
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dot" => Ok(Self::Dot),
+            "graphml" => Ok(Self::GraphMl),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown graph format '{s}' (expected dot, graphml or json)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dot => "dot",
+            Self::GraphMl => "graphml",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// Options controlling the graph export.
+#[derive(Debug, Clone, Default)]
+pub struct GraphOptions {
+    /// Output format.
+    pub format: GraphFormat,
+
+    /// Only export entities reachable from these entity IDs.
+    pub from_ids: Vec<u32>,
+
+    /// Only export entities reachable from entities of these types.
+    ///
+    /// Combined with `from_ids`; if both are empty, the whole graph is
+    /// exported.
+    pub from_types: Vec<String>,
+
+    /// Entity types highlighted as GC roots in addition to
+    /// [`GC_ROOT_ENTITIES`](crate::GC_ROOT_ENTITIES), as in
+    /// [`ReduceOptions::root_entities`](crate::ReduceOptions::root_entities).
+    pub root_entities: Vec<String>,
+
+    /// Bounds on the input, as in
+    /// [`ReduceOptions::limits`](crate::ReduceOptions::limits).
+    pub limits: Limits,
+}
+
+struct Node<'a> {
+    entity_types: Vec<&'a str>,
+    label: String,
+    root: bool,
+    refs: BTreeSet<u32>,
+}
+
+/// Build the node table, keyed by entity ID.
+fn build_nodes<'a>(lines: &'a [impl Instance], roots: &[String]) -> BTreeMap<u32, Node<'a>> {
+    let mut nodes = BTreeMap::new();
+    for line in lines {
+        let Some((eid, rhs)) = line.instance() else {
+            continue;
+        };
+        let entity_types = get_entity_types(rhs);
+        nodes.insert(
+            eid,
+            Node {
+                label: entity_type_label(rhs),
+                root: is_gc_root(&entity_types, roots),
+                entity_types,
+                refs: collect_references(rhs).into_iter().collect(),
+            },
+        );
+    }
+    nodes
+}
+
+/// Select the IDs to export: everything reachable from the seeds, or all
+/// nodes if no seeds were requested.
+fn select(nodes: &BTreeMap<u32, Node<'_>>, options: &GraphOptions) -> BTreeSet<u32> {
+    if options.from_ids.is_empty() && options.from_types.is_empty() {
+        return nodes.keys().copied().collect();
+    }
+
+    let mut stack: Vec<u32> = options
+        .from_ids
+        .iter()
+        .copied()
+        .filter(|eid| nodes.contains_key(eid))
+        .chain(
+            nodes
+                .iter()
+                .filter(|(_, n)| {
+                    n.entity_types
+                        .iter()
+                        .any(|t| options.from_types.iter().any(|f| f == t))
+                })
+                .map(|(&eid, _)| eid),
+        )
+        .collect();
+    let mut selected: BTreeSet<u32> = stack.iter().copied().collect();
+
+    while let Some(eid) = stack.pop() {
+        for &r in &nodes[&eid].refs {
+            if nodes.contains_key(&r) && selected.insert(r) {
+                stack.push(r);
+            }
+        }
+    }
+
+    selected
+}
+
+/// Escape a string for use inside an XML attribute or text node.
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape a string for use inside a JSON string literal.
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_dot(out: &mut String, nodes: &BTreeMap<u32, Node<'_>>, selected: &BTreeSet<u32>) {
+    out.push_str("digraph step {\n  node [shape=box];\n");
+    for &eid in selected {
+        let node = &nodes[&eid];
+        let root = if node.root {
+            ", style=filled, fillcolor=lightblue"
+        } else {
+            ""
+        };
+        // DOT string literals use the same quote and backslash escapes as JSON.
+        writeln!(
+            out,
+            "  n{eid} [label=\"#{eid}\\n{}\"{root}];",
+            escape_json(&node.label)
+        )
+        .unwrap();
+    }
+    for &eid in selected {
+        for r in nodes[&eid].refs.iter().filter(|r| selected.contains(r)) {
+            writeln!(out, "  n{eid} -> n{r};").unwrap();
+        }
+    }
+    out.push_str("}\n");
+}
+
+fn write_graphml(out: &mut String, nodes: &BTreeMap<u32, Node<'_>>, selected: &BTreeSet<u32>) {
+    out.push_str(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"type\" for=\"node\" attr.name=\"type\" attr.type=\"string\"/>\n",
+        "  <key id=\"root\" for=\"node\" attr.name=\"root\" attr.type=\"boolean\"/>\n",
+        "  <graph id=\"step\" edgedefault=\"directed\">\n",
+    ));
+    for &eid in selected {
+        let node = &nodes[&eid];
+        writeln!(
+            out,
+            "    <node id=\"n{eid}\"><data key=\"type\">{}</data><data key=\"root\">{}</data></node>",
+            escape_xml(&node.label),
+            node.root,
+        )
+        .unwrap();
+    }
+    for &eid in selected {
+        for r in nodes[&eid].refs.iter().filter(|r| selected.contains(r)) {
+            writeln!(out, "    <edge source=\"n{eid}\" target=\"n{r}\"/>").unwrap();
+        }
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+}
+
+fn write_json(out: &mut String, nodes: &BTreeMap<u32, Node<'_>>, selected: &BTreeSet<u32>) {
+    out.push_str("{\n  \"nodes\": [");
+    for (i, &eid) in selected.iter().enumerate() {
+        let node = &nodes[&eid];
+        let sep = if i == 0 { "" } else { "," };
+        write!(
+            out,
+            "{sep}\n    {{\"id\": {eid}, \"type\": \"{}\", \"root\": {}}}",
+            escape_json(&node.label),
+            node.root,
+        )
+        .unwrap();
+    }
+    out.push_str("\n  ],\n  \"edges\": [");
+    let mut first = true;
+    for &eid in selected {
+        for r in nodes[&eid].refs.iter().filter(|r| selected.contains(r)) {
+            let sep = if first { "" } else { "," };
+            first = false;
+            write!(out, "{sep}\n    {{\"source\": {eid}, \"target\": {r}}}").unwrap();
+        }
+    }
+    out.push_str("\n  ]\n}\n");
+}
+
+/// Render the reference graph of the entities `lines`.
+pub(crate) fn render_graph(lines: &[impl Instance], options: &GraphOptions) -> String {
+    let nodes = build_nodes(lines, &options.root_entities);
+    let selected = select(&nodes, options);
+
+    let mut out = String::new();
+    match options.format {
+        GraphFormat::Dot => write_dot(&mut out, &nodes, &selected),
+        GraphFormat::GraphMl => write_graphml(&mut out, &nodes, &selected),
+        GraphFormat::Json => write_json(&mut out, &nodes, &selected),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, LimitError};
+
+    const INPUT: &str = "\
+DATA;
+#1=PRODUCT_DEFINITION('pd',#2);
+#2=CARTESIAN_POINT('',0.,0.,0.);
+#3=DIRECTION('',1.,0.,0.);
+ENDSEC;
+";
+
+    fn render(options: GraphOptions) -> String {
+        crate::export_graph(INPUT.as_bytes(), &options).unwrap()
+    }
+
+    #[test]
+    fn dot() {
+        let out = render(GraphOptions::default());
+        assert_eq!(
+            out,
+            "digraph step {
+  node [shape=box];
+  n1 [label=\"#1\\nPRODUCT_DEFINITION\", style=filled, fillcolor=lightblue];
+  n2 [label=\"#2\\nCARTESIAN_POINT\"];
+  n3 [label=\"#3\\nDIRECTION\"];
+  n1 -> n2;
+}
+"
+        );
+    }
+
+    #[test]
+    fn json_reachable_from_type() {
+        let out = render(GraphOptions {
+            format: GraphFormat::Json,
+            from_types: vec!["PRODUCT_DEFINITION".to_string()],
+            ..Default::default()
+        });
+        assert!(out.contains("{\"id\": 2, \"type\": \"CARTESIAN_POINT\", \"root\": false}"));
+        assert!(!out.contains("DIRECTION"));
+        assert!(out.contains("{\"source\": 1, \"target\": 2}"));
+    }
+
+    #[test]
+    fn graphml_reachable_from_id() {
+        let out = render(GraphOptions {
+            format: GraphFormat::GraphMl,
+            from_ids: vec![3],
+            ..Default::default()
+        });
+        assert!(out.contains("<node id=\"n3\">"));
+        assert!(!out.contains("<node id=\"n1\">"));
+        assert!(!out.contains("<edge"));
+    }
+
+    #[test]
+    fn extra_roots() {
+        let out = render(GraphOptions {
+            format: GraphFormat::Json,
+            root_entities: vec!["DIRECTION".to_string()],
+            ..Default::default()
+        });
+        assert!(out.contains("{\"id\": 3, \"type\": \"DIRECTION\", \"root\": true}"));
+    }
+
+    #[test]
+    fn limits() {
+        let options = GraphOptions {
+            limits: Limits {
+                max_entities: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = crate::export_graph(INPUT.as_bytes(), &options).unwrap_err();
+        assert!(
+            matches!(err, Error::Limit(LimitError::EntityCount { limit: 2, .. })),
+            "{err}"
+        );
+    }
+}
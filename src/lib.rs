@@ -1,7 +1,13 @@
 //! Reduce STEP CAD file size by deduplicating entities and removing orphans.
 //!
-//! This is a lossless, one-way compression / redundancy-reduction algorithm
-//! targeted at STEP (ISO 10303-21) files.
+//! This is a one-way compression / redundancy-reduction algorithm targeted
+//! at STEP (ISO 10303-21) files. With the default options the geometry is
+//! kept exactly, only the names of merged entities are dropped (see
+//! [`ReduceOptions::names`]). Any of the precision options
+//! ([`ReduceOptions::precision`], [`ReduceOptions::use_step_precision`] and
+//! the per-type overrides) make it lossy: entities whose numbers agree at
+//! that precision are merged, and [`ReduceOptions::quantize`] also rounds
+//! every number in the output.
 //!
 //! # Example
 //!
@@ -10,68 +16,307 @@
 //!
 //! let step_data = b"ISO-10303-21;\nHEADER;\nENDSEC;\nDATA;\n#1=FOO('x');\nENDSEC;\nEND-ISO-10303-21;\n";
 //! let opts = ReduceOptions::default();
-//! let reduced = reduce(step_data, &opts);
+//! let reduced = reduce(step_data, &opts).unwrap();
 //! assert!(!reduced.is_empty());
 //! ```
 
-use std::io::Write;
+use std::collections::HashMap;
 
+#[cfg(feature = "serde")]
+mod config;
+mod context;
 mod deduplicate;
+mod error;
+mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod find_numbers;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+mod graph;
+mod limits;
+mod model;
 mod normalize;
 mod orphans;
 mod parse;
+mod pipeline;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
 mod references;
+mod stats;
+#[cfg(test)]
+mod test_utils;
+mod validate;
+mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use context::ContextDecimals;
+pub use deduplicate::{IDENTITY_ENTITIES, NameHandling};
+pub use error::Error;
+pub use explain::{Explanation, MergeStep, Outcome, PathStep};
+pub use graph::{GraphFormat, GraphOptions};
+pub use limits::{LimitError, Limits};
+pub use model::{Entity, Header, Parameter, ParseError, Real, Record, StepFile};
+pub use normalize::Precision;
+use normalize::PrecisionPolicy;
+pub use orphans::{DEFAULT_ANNOTATION_ENTITIES, GC_ROOT_ENTITIES};
+pub use pipeline::{
+    Deduplicate, Pass, PassContext, Pipeline, Quantize, RemoveOrphans, ShortenReals,
+};
+pub use progress::{CancellationToken, Phase, ProgressCallback};
+pub use stats::{Distribution, FileStats, ReduceStats, TypeStats};
+pub use validate::{Issue, ValidationReport};
+pub use verify::{VerifyError, Violation};
 
 /// Options controlling the reduction process.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields, rename_all = "kebab-case")
+)]
 pub struct ReduceOptions {
-    /// Maximum number of decimal places for numeric comparison.
+    /// Precision for numeric comparison.
     ///
     /// `None` means no rounding — numbers are only normalized (scientific
     /// notation expanded, redundant zeros stripped).
-    pub max_decimals: Option<u32>,
+    pub precision: Option<Precision>,
+
+    /// Precision overrides by entity type (e.g. `DIRECTION`). Each partial of
+    /// a complex instance uses its own type. Overrides win over `precision`
+    /// and `use_step_precision`.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "config::type_precision"))]
+    pub type_precision: HashMap<String, Precision>,
+
+    /// Precision overrides by entity type and zero-based attribute position,
+    /// e.g. `("CIRCLE", 2)` for circle radii. Numbers nested in lists or typed
+    /// parameters belong to the enclosing attribute. These win over
+    /// `type_precision`.
+    #[cfg_attr(feature = "serde", serde(with = "config::attribute_precision"))]
+    pub attribute_precision: HashMap<(String, usize), Precision>,
 
-    /// Derive precision from the STEP file's
-    /// `UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(…))` value.
+    /// Derive precision from the `UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(…))`
+    /// of each entity's representation context, converted to the context's
+    /// length unit, as a number of decimal places. Entities of contexts with
+    /// different uncertainties are never merged with each other.
     ///
-    /// When both this and `max_decimals` are set, the smaller value wins.
+    /// When `precision` is also set to [`Precision::DecimalPlaces`], the
+    /// smaller value wins; other precision modes take precedence over the
+    /// derived one.
     pub use_step_precision: bool,
+
+    /// Whether entity names take part in deduplication.
+    ///
+    /// The default, [`NameHandling::Ignore`], merges entities that differ only
+    /// in their name and keeps the first entity's name; see
+    /// [`ReduceStats::labels_lost`] for how many names were dropped.
+    pub names: NameHandling,
+
+    /// Entity types that carry identity, in addition to
+    /// [`IDENTITY_ENTITIES`]: they are never merged, even if equal.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "config::entity_types"))]
+    pub identity_entities: Vec<String>,
+
+    /// Entity types that are GC roots, in addition to [`GC_ROOT_ENTITIES`]:
+    /// they and everything they reference are kept.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "config::entity_types"))]
+    pub root_entities: Vec<String>,
+
+    /// Entity types that are kept even when unreachable from a GC root, as
+    /// long as they annotate kept entities (e.g. a `STYLED_ITEM` colouring a
+    /// kept face) and reference nothing but kept entities and their own
+    /// data. Their own data, such as styles or property values, is kept with
+    /// them; other unreachable entities are not. See
+    /// [`DEFAULT_ANNOTATION_ENTITIES`] for a sensible list.
+    ///
+    /// Empty by default, which only keeps entities reachable from GC roots.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "config::entity_types"))]
+    pub annotation_entities: Vec<String>,
+
+    /// Rewrite every REAL literal in the output to the shortest form with
+    /// exactly the same decimal value, e.g. `1.00000000000000E+000` → `1.`
+    /// and `0.0000001` → `1.E-7`. Lossless, but the output is no longer a
+    /// textual subset of the input.
+    pub shorten_reals: bool,
+
+    /// Write every REAL literal in the output rounded to the effective
+    /// precision (`precision`, possibly lowered by `use_step_precision`).
+    ///
+    /// Lossy. Numbers never lose more fractional digits than the file's
+    /// declared `UNCERTAINTY_MEASURE_WITH_UNIT` allows; see
+    /// [`ReduceStats::quantize_min_decimals`]. Does nothing if no precision is
+    /// set.
+    pub quantize: bool,
+
+    /// Check that the reduced entity graph is a quotient of the original one
+    /// (see [`VerifyError`]) and fail with [`Error::Verify`] otherwise.
+    pub verify: bool,
+
+    /// Bounds on the input; exceeding one fails with [`Error::Limit`].
+    pub limits: Limits,
+
+    /// Maximum number of rounds of a [`Pipeline`] that repeats its passes
+    /// until none changes anything; `None` means no limit. One round always
+    /// runs. The standard passes finish in one round, since removing orphans
+    /// never makes more entities equal.
+    pub max_rounds: Option<usize>,
+
+    /// Called to report the progress of the reduction, e.g. to show a
+    /// progress bar.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub progress: Option<ProgressCallback>,
+
+    /// Checked regularly during the reduction, which fails with
+    /// [`Error::Cancelled`] once the token is cancelled.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cancel: Option<CancellationToken>,
 }
 
-/// Reduce a STEP file by deduplicating entities and removing orphans.
+/// Check a STEP file for referential-integrity problems.
 ///
-/// Accepts raw STEP file content as a byte slice and returns the reduced
-/// content as a `Vec<u8>`.
-pub fn reduce(input: &[u8], options: &ReduceOptions) -> Vec<u8> {
-    let reader = std::io::Cursor::new(input);
-    let parsed = parse::parse_data_section(reader);
-
-    let mut max_decimals = options.max_decimals;
-
-    if options.use_step_precision
-        && let Some(step_decimals) = normalize::extract_uncertainty(&parsed.data)
-    {
-        max_decimals = Some(match max_decimals {
-            Some(current) => current.min(step_decimals),
-            None => step_decimals,
-        });
-    }
+/// Reports dangling references, duplicate instance IDs, self-references,
+/// unparseable data lines and missing mandatory header entities
+/// (`FILE_DESCRIPTION`, `FILE_NAME`, `FILE_SCHEMA`).
+pub fn validate(input: &[u8]) -> ValidationReport {
+    let parsed = parse::parse_data_section(input);
+    validate::validate_parsed(&parsed)
+}
+
+/// Explain why entity `id` was kept, merged or removed by [`reduce`].
+///
+/// Merges are reported as a chain (an entity may be merged into one that is
+/// itself merged in a later pass). For kept entities, the reference path
+/// from a GC root is included. Fails like [`reduce`].
+pub fn explain(input: &[u8], id: u32, options: &ReduceOptions) -> Result<Explanation, Error> {
+    let file = StepFile::parse_with_limits(input, &options.limits)?;
+    explain::explain_entity(file, id, options)
+}
 
-    let data_lines = deduplicate::deduplicate(&parsed.data, max_decimals);
-    let data_lines = orphans::remove_orphans(&data_lines);
+/// Export the entity reference graph, with entities as nodes labelled by
+/// their type and `#NNN` references as edges. GC roots are highlighted.
+/// Fails with [`Error::Parse`] or [`Error::Limit`] like [`reduce`].
+pub fn export_graph(input: &[u8], options: &GraphOptions) -> Result<String, Error> {
+    let file = StepFile::parse_with_limits(input, &options.limits)?;
+    Ok(graph::render_graph(
+        &pipeline::instances(&file.entities),
+        options,
+    ))
+}
 
-    let mut output = Vec::with_capacity(input.len());
+/// Collect per-entity-type statistics without reducing the file.
+///
+/// The duplicate counts reflect what [`reduce`] would merge with the given
+/// options; unreachable counts reflect what orphan removal would drop.
+/// Fails like [`reduce`].
+pub fn stats(input: &[u8], options: &ReduceOptions) -> Result<FileStats, Error> {
+    let file = StepFile::parse_with_limits(input, &options.limits)?;
+    stats::collect_stats(&file, options)
+}
 
-    for line in &parsed.header {
-        writeln!(output, "{line}").expect("writing to Vec through Cursor should not fail");
+/// Check that every precision of `options` is usable: significant digits
+/// must be at least 1, and tolerances positive and finite.
+fn check_options(options: &ReduceOptions) -> Result<(), Error> {
+    let precisions = (options.precision.iter())
+        .chain(options.type_precision.values())
+        .chain(options.attribute_precision.values());
+    for precision in precisions {
+        match *precision {
+            Precision::SignificantDigits(0) => {
+                return Err(Error::InvalidOptions(
+                    "precision needs at least 1 significant digit".to_string(),
+                ));
+            }
+            Precision::Absolute(eps) if !(eps > 0.0 && eps.is_finite()) => {
+                return Err(Error::InvalidOptions(format!(
+                    "tolerance {eps} is not a positive number"
+                )));
+            }
+            _ => {}
+        }
     }
-    for line in &data_lines {
-        writeln!(output, "{line}").expect("writing to Vec through Cursor should not fail");
+    Ok(())
+}
+
+/// Combine `precision` with decimal places derived from an uncertainty.
+fn combine_precision(
+    precision: Option<Precision>,
+    step_decimals: Option<u32>,
+) -> Option<Precision> {
+    match (precision, step_decimals) {
+        (Some(Precision::DecimalPlaces(current)), Some(step)) => {
+            Some(Precision::DecimalPlaces(current.min(step)))
+        }
+        (None, Some(step)) => Some(Precision::DecimalPlaces(step)),
+        (precision, _) => precision,
     }
-    for line in &parsed.footer {
-        writeln!(output, "{line}").expect("writing to Vec through Cursor should not fail");
+}
+
+/// Build the precision policy: `precision`, combined with the precision
+/// derived from each entity's representation context if requested, plus the
+/// per-type overrides.
+fn precision_policy(data: &[impl parse::Instance], options: &ReduceOptions) -> PrecisionPolicy {
+    let contexts = if options.use_step_precision {
+        context::context_decimals(data)
+    } else {
+        ContextDecimals::default()
+    };
+    let default = combine_precision(options.precision, contexts.fallback);
+    let entities = contexts
+        .entities
+        .iter()
+        .filter_map(|(&id, &decimals)| {
+            Some((id, combine_precision(options.precision, Some(decimals))?))
+        })
+        .collect();
+
+    let mut attributes: HashMap<String, HashMap<usize, Precision>> = HashMap::new();
+    for ((entity_type, position), &precision) in &options.attribute_precision {
+        attributes
+            .entry(entity_type.clone())
+            .or_default()
+            .insert(*position, precision);
     }
 
-    output
+    PrecisionPolicy {
+        default,
+        types: options.type_precision.clone(),
+        attributes,
+        entities,
+    }
+}
+
+/// Reduce a STEP file by deduplicating entities and removing orphans.
+///
+/// Accepts raw STEP file content as a byte slice and returns the reduced
+/// content as a `Vec<u8>`.
+pub fn reduce(input: &[u8], options: &ReduceOptions) -> Result<Vec<u8>, Error> {
+    reduce_with_stats(input, options).map(|(output, _)| output)
+}
+
+/// Like [`reduce`], but also returns a summary of what was merged and removed.
+pub fn reduce_with_stats(
+    input: &[u8],
+    options: &ReduceOptions,
+) -> Result<(Vec<u8>, ReduceStats), Error> {
+    Pipeline::standard(options.clone()).run(input)
+}
+
+/// Like [`reduce_with_stats`], but memory-map the file at `path` instead of
+/// reading it into memory (see [`StepFile::parse_file`]). Fails with
+/// [`Error::Io`] if the file cannot be opened or mapped.
+///
+/// # Safety
+///
+/// The file must not be modified until this returns, see
+/// [`StepFile::parse_file`].
+#[cfg(feature = "mmap")]
+pub unsafe fn reduce_file(
+    path: impl AsRef<std::path::Path>,
+    options: &ReduceOptions,
+) -> Result<(Vec<u8>, ReduceStats), Error> {
+    // SAFETY: passed on to the caller.
+    unsafe { Pipeline::standard(options.clone()).run_path(path) }
 }
@@ -0,0 +1,77 @@
+//! Resource limits for untrusted input.
+
+use std::fmt;
+
+/// Upper bounds on the input accepted by [`reduce`](crate::reduce).
+///
+/// All limits are off by default. Set them when reducing untrusted files so
+/// that hostile input fails with [`Error::Limit`](crate::Error::Limit)
+/// instead of exhausting memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields, rename_all = "kebab-case")
+)]
+pub struct Limits {
+    /// Maximum input size in bytes.
+    pub max_file_size: Option<usize>,
+
+    /// Maximum length in bytes of a physical line, and of an entity after
+    /// its continuation lines are joined.
+    pub max_line_length: Option<usize>,
+
+    /// Maximum number of entities in the `DATA` section.
+    pub max_entities: Option<usize>,
+
+    /// Maximum absolute decimal exponent of a number literal, e.g. `308` to
+    /// accept everything representable as an `f64`.
+    pub max_exponent: Option<u32>,
+}
+
+/// The input exceeds one of the configured [`Limits`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitError {
+    /// The input is larger than `max_file_size`.
+    FileSize { size: usize, limit: usize },
+    /// A line or entity starting at (1-based) `line` is longer than
+    /// `max_line_length`.
+    LineLength { line: usize, limit: usize },
+    /// The `DATA` section has more than `max_entities` entities; `line` is
+    /// where the first entity over the limit starts.
+    EntityCount { line: usize, limit: usize },
+    /// The entity starting at `line` contains a number literal whose exponent
+    /// exceeds `max_exponent`.
+    Exponent {
+        line: usize,
+        literal: String,
+        limit: u32,
+    },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileSize { size, limit } => {
+                write!(f, "input is {size} bytes, more than the limit of {limit}")
+            }
+            Self::LineLength { line, limit } => {
+                write!(f, "line {line} is longer than {limit} bytes")
+            }
+            Self::EntityCount { line, limit } => {
+                write!(f, "line {line}: more than {limit} entities")
+            }
+            Self::Exponent {
+                line,
+                literal,
+                limit,
+            } => write!(
+                f,
+                "line {line}: exponent of {literal} exceeds the limit of {limit}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
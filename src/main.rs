@@ -1,57 +1,589 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
-use anyhow::Context;
-use clap::Parser;
+use anyhow::{Context, bail};
+use clap::{Args, Parser, Subcommand};
 
-use stepreduce::ReduceOptions;
+use stepreduce::{
+    DEFAULT_ANNOTATION_ENTITIES, Distribution, GraphFormat, GraphOptions, NameHandling, Precision,
+    ProgressCallback, ReduceOptions,
+};
 
 /// Reduce STEP file size by deduplicating entities and removing orphans.
 #[derive(Parser)]
-#[command(name = "stepreduce", version)]
+#[command(
+    name = "stepreduce",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    reduce: ReduceArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check a STEP file for dangling references, duplicate IDs and other
+    /// integrity problems.
+    Validate {
+        /// STEP file to check.
+        input: PathBuf,
+    },
+
+    /// Print entity counts, sizes, duplicate and unreachable counts per entity
+    /// type, without writing any output.
+    Stats {
+        /// STEP file to analyze.
+        input: PathBuf,
+
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+
+    /// Explain why an entity was kept, merged or removed.
+    Explain {
+        /// STEP file to analyze.
+        input: PathBuf,
+
+        /// Entity ID (as in the input file) to explain.
+        #[arg(long)]
+        id: u32,
+
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+
+    /// Export the entity reference graph.
+    Graph {
+        /// STEP file to export.
+        input: PathBuf,
+
+        /// Output format: dot, graphml or json.
+        #[arg(short, long, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+
+        /// Only export entities reachable from this entity ID (repeatable).
+        #[arg(long = "from-id", value_name = "ID")]
+        from_ids: Vec<u32>,
+
+        /// Only export entities reachable from entities of this type (repeatable).
+        #[arg(long = "from-type", value_name = "TYPE", value_parser = parse_entity_type)]
+        from_types: Vec<String>,
+
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Additional entity type that is highlighted as a GC root, as for
+        /// reduce (repeatable).
+        #[arg(long = "root-type", value_name = "TYPE", value_parser = parse_entity_type)]
+        root_types: Vec<String>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+}
+
+/// The name of the config file looked up next to the input file.
+const CONFIG_FILE: &str = "stepreduce.toml";
+
+#[derive(Args)]
+struct ConfigArgs {
+    /// Read options from this file instead of the nearest stepreduce.toml in
+    /// the input file's directory or one of its parents. Flags override the
+    /// file.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Don't read any config file.
+    #[arg(long, conflicts_with = "config")]
+    no_config: bool,
+}
+
+#[derive(Args)]
+struct OptionArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// Maximum decimal places for numeric comparison.
+    #[arg(short, long, conflicts_with_all = ["significant_digits", "tolerance"])]
+    precision: Option<u32>,
+
+    /// Maximum significant digits for numeric comparison.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "tolerance",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    significant_digits: Option<u32>,
+
+    /// Absolute tolerance for numeric comparison; numbers are rounded to
+    /// multiples of it.
+    #[arg(long, value_name = "EPS", value_parser = parse_tolerance)]
+    tolerance: Option<f64>,
+
+    /// Precision for one entity type, or one attribute (zero-based) of it, as
+    /// TYPE=P or TYPE[N]=P, where P is N (decimal places), sig:N or tol:EPS
+    /// (repeatable).
+    #[arg(long = "type-precision", value_name = "SPEC", value_parser = parse_type_precision)]
+    type_precisions: Vec<(String, Option<usize>, Precision)>,
+
+    /// Derive precision from the uncertainty of each entity's representation
+    /// context, converted to the context's length unit.
+    #[arg(long, overrides_with = "no_use_step_precision")]
+    use_step_precision: bool,
+
+    /// Don't derive precision from the file, even if the config file does.
+    #[arg(long, overrides_with = "use_step_precision")]
+    no_use_step_precision: bool,
+
+    /// Entity names in deduplication: ignore (default), preserve or
+    /// preserve-non-empty.
+    #[arg(long)]
+    names: Option<NameHandling>,
+
+    /// Keep styling and property entities (STYLED_ITEM, PROPERTY_DEFINITION, …)
+    /// that annotate kept entities, even if no GC root references them.
+    #[arg(long)]
+    keep_annotations: bool,
+
+    /// Additional entity type to keep when it annotates kept entities (repeatable).
+    #[arg(long = "annotation-type", value_name = "TYPE", value_parser = parse_entity_type)]
+    annotation_types: Vec<String>,
+
+    /// Additional entity type that carries identity and is never merged
+    /// (repeatable).
+    #[arg(long = "identity-type", value_name = "TYPE", value_parser = parse_entity_type)]
+    identity_types: Vec<String>,
+
+    /// Additional entity type that is a GC root, keeping everything it
+    /// references (repeatable).
+    #[arg(long = "root-type", value_name = "TYPE", value_parser = parse_entity_type)]
+    root_types: Vec<String>,
+}
+
+fn parse_entity_type(s: &str) -> Result<String, String> {
+    Ok(s.to_ascii_uppercase())
+}
+
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(eps) if eps > 0.0 && eps.is_finite() => Ok(eps),
+        _ => Err(format!("'{s}' is not a positive number")),
+    }
+}
+
+fn parse_type_precision(s: &str) -> Result<(String, Option<usize>, Precision), String> {
+    let (target, precision) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{s}' is not of the form TYPE=P or TYPE[N]=P"))?;
+    let precision = precision.parse()?;
+    let (entity_type, position) = match target.strip_suffix(']') {
+        Some(rest) => {
+            let (entity_type, position) = rest
+                .split_once('[')
+                .ok_or_else(|| format!("'{target}' is not of the form TYPE[N]"))?;
+            let position = position
+                .parse()
+                .map_err(|_| format!("'{position}' is not an attribute position"))?;
+            (entity_type, Some(position))
+        }
+        None => (target, None),
+    };
+    Ok((entity_type.to_ascii_uppercase(), position, precision))
+}
+
+/// The value of an option that `--FLAG` switches on and `--no-FLAG` off (the
+/// last one given wins), or else `config`.
+fn switch(on: bool, off: bool, config: bool) -> bool {
+    on || (config && !off)
+}
+
+/// Add the entity types of `extra` to `types` that are not in it yet.
+fn extend_types(types: &mut Vec<String>, extra: &[String]) {
+    for entity_type in extra {
+        if !types.contains(entity_type) {
+            types.push(entity_type.clone());
+        }
+    }
+}
+
+impl ConfigArgs {
+    /// The config file for `input`: `--config`, or else the nearest
+    /// stepreduce.toml in the directory of `input` or one of its parents.
+    fn config_file(&self, input: &Path) -> Option<PathBuf> {
+        if self.no_config {
+            return None;
+        }
+        if let Some(config) = &self.config {
+            return Some(config.clone());
+        }
+        let input = std::path::absolute(input).ok()?;
+        input
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// The options in the config file for `input`, or the default ones.
+    fn load(&self, input: &Path) -> anyhow::Result<ReduceOptions> {
+        let Some(path) = self.config_file(input) else {
+            return Ok(ReduceOptions::default());
+        };
+        let config = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&config).with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
+
+impl OptionArgs {
+    /// The options for `input`: those of its config file, overridden by the
+    /// flags.
+    fn to_options(&self, input: &Path) -> anyhow::Result<ReduceOptions> {
+        let mut options = self.config.load(input)?;
+
+        if let Some(precision) = self
+            .precision
+            .map(Precision::DecimalPlaces)
+            .or(self.significant_digits.map(Precision::SignificantDigits))
+            .or(self.tolerance.map(Precision::Absolute))
+        {
+            options.precision = Some(precision);
+        }
+        for (entity_type, position, precision) in &self.type_precisions {
+            match position {
+                Some(position) => {
+                    options
+                        .attribute_precision
+                        .insert((entity_type.clone(), *position), *precision);
+                }
+                None => {
+                    options
+                        .type_precision
+                        .insert(entity_type.clone(), *precision);
+                }
+            }
+        }
+        options.use_step_precision = switch(
+            self.use_step_precision,
+            self.no_use_step_precision,
+            options.use_step_precision,
+        );
+        if let Some(names) = self.names {
+            options.names = names;
+        }
+
+        let mut annotation_types = self.annotation_types.clone();
+        if self.keep_annotations {
+            annotation_types.extend(DEFAULT_ANNOTATION_ENTITIES.iter().map(|t| t.to_string()));
+        }
+        extend_types(&mut options.annotation_entities, &annotation_types);
+        extend_types(&mut options.identity_entities, &self.identity_types);
+        extend_types(&mut options.root_entities, &self.root_types);
+
+        Ok(options)
+    }
+}
+
+#[derive(Args)]
+struct ReduceArgs {
     /// Input STEP file.
-    input: PathBuf,
+    #[arg(required = true)]
+    input: Option<PathBuf>,
 
     /// Output STEP file (may be the same as input).
-    output: PathBuf,
+    #[arg(required = true)]
+    output: Option<PathBuf>,
 
     /// Print reduction statistics.
     #[arg(short, long)]
     verbose: bool,
 
-    /// Maximum decimal places for numeric comparison.
-    #[arg(short, long)]
-    precision: Option<u32>,
-
-    /// Derive precision from the STEP file's UNCERTAINTY_MEASURE_WITH_UNIT value.
+    /// Don't show a progress bar (only shown if stderr is a terminal).
     #[arg(long)]
-    use_step_precision: bool,
+    no_progress: bool,
+
+    #[command(flatten)]
+    options: OptionArgs,
+
+    /// Rewrite numbers to their shortest exact form (e.g. 1.00000000000000E+000 → 1.).
+    #[arg(long, overrides_with = "no_shorten_reals")]
+    shorten_reals: bool,
+
+    /// Keep numbers as written, even if the config file shortens them.
+    #[arg(long, overrides_with = "shorten_reals")]
+    no_shorten_reals: bool,
+
+    /// Write numbers rounded to the effective precision (lossy; never below
+    /// the file's declared uncertainty).
+    #[arg(long, overrides_with = "no_quantize")]
+    quantize: bool,
+
+    /// Don't round the numbers written, even if the config file does.
+    #[arg(long, overrides_with = "quantize")]
+    no_quantize: bool,
+
+    /// Check that the output is a lossless quotient of the input and fail otherwise.
+    #[arg(long, overrides_with = "no_verify")]
+    verify: bool,
+
+    /// Don't check the output, even if the config file does.
+    #[arg(long, overrides_with = "verify")]
+    no_verify: bool,
+
+    /// Refuse input files larger than this many bytes.
+    #[arg(long, value_name = "BYTES")]
+    max_file_size: Option<usize>,
+
+    /// Refuse lines or entities longer than this many bytes.
+    #[arg(long, value_name = "BYTES")]
+    max_line_length: Option<usize>,
+
+    /// Refuse files with more than this many entities.
+    #[arg(long, value_name = "N")]
+    max_entities: Option<usize>,
+
+    /// Refuse number literals with a larger absolute decimal exponent.
+    #[arg(long, value_name = "N")]
+    max_exponent: Option<u32>,
 }
 
-fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+/// A progress bar on stderr, redrawn whenever the phase or percentage
+/// changes.
+fn progress_bar() -> ProgressCallback {
+    const WIDTH: usize = 30;
+    let last = Mutex::new(None);
+    ProgressCallback::new(move |phase, fraction| {
+        let percent = (fraction * 100.0) as u32;
+        let mut last = last.lock().unwrap();
+        if *last == Some((phase, percent)) {
+            return;
+        }
+        *last = Some((phase, percent));
+        let filled = (fraction * WIDTH as f64) as usize;
+        eprint!(
+            "\r[{}{}] {percent:>3}% {phase:<24}",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled)
+        );
+    })
+}
+
+fn read_input(path: &Path) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
 
-    let options = ReduceOptions {
-        max_decimals: cli.precision,
-        use_step_precision: cli.use_step_precision,
+fn run_reduce(args: ReduceArgs) -> anyhow::Result<()> {
+    let (Some(input), Some(output)) = (args.input, args.output) else {
+        unreachable!("clap enforces input and output without a subcommand");
     };
 
-    let input_data =
-        fs::read(&cli.input).with_context(|| format!("failed to read {}", cli.input.display()))?;
+    let mut options = args.options.to_options(&input)?;
+    options.shorten_reals = switch(
+        args.shorten_reals,
+        args.no_shorten_reals,
+        options.shorten_reals,
+    );
+    options.quantize = switch(args.quantize, args.no_quantize, options.quantize);
+    options.verify = switch(args.verify, args.no_verify, options.verify);
+    let limits = &mut options.limits;
+    limits.max_file_size = args.max_file_size.or(limits.max_file_size);
+    limits.max_line_length = args.max_line_length.or(limits.max_line_length);
+    limits.max_entities = args.max_entities.or(limits.max_entities);
+    limits.max_exponent = args.max_exponent.or(limits.max_exponent);
 
-    let output_data = stepreduce::reduce(&input_data, &options);
+    let progress = !args.no_progress && std::io::stderr().is_terminal();
+    if progress {
+        options.progress = Some(progress_bar());
+    }
+    let input_size = fs::metadata(&input)
+        .with_context(|| format!("failed to read {}", input.display()))?
+        .len();
+    // Memory-mapped, and checked against the size limit before mapping.
+    // SAFETY: like other command-line tools that map their input, stepreduce
+    // requires that the input is not modified while it runs.
+    let result = unsafe { stepreduce::reduce_file(&input, &options) };
+    if progress {
+        // Clear the progress bar.
+        eprint!("\r\x1b[2K");
+    }
+    let (output_data, stats) =
+        result.with_context(|| format!("failed to reduce {}", input.display()))?;
 
-    fs::write(&cli.output, &output_data)
-        .with_context(|| format!("failed to write {}", cli.output.display()))?;
+    fs::write(&output, &output_data)
+        .with_context(|| format!("failed to write {}", output.display()))?;
 
     // Print stats
-    if cli.verbose {
-        let before = input_data.len();
+    if args.verbose {
+        if let Some(config) = args.options.config.config_file(&input) {
+            println!("Options from {}", config.display());
+        }
+        let before = input_size as usize;
         let after = output_data.len();
-        let delta = before - after;
-        let percent = (delta as f32) * 100.0 / (before as f32);
-        println!("Done: {before} bytes shrunk to {after} bytes (-{percent:.1}%)");
+        println!(
+            "{} entities: {} merged, {} removed, {} kept",
+            stats.input_entities, stats.merged, stats.removed, stats.output_entities
+        );
+        if stats.labels_lost > 0 {
+            println!(
+                "{} entity names lost in merges (use --names preserve to keep them)",
+                stats.labels_lost
+            );
+        }
+        if stats.quantized {
+            match stats.quantize_min_decimals {
+                Some(decimals) => {
+                    println!("Numbers quantized, keeping at least {decimals} decimal places")
+                }
+                None => println!("Numbers quantized"),
+            }
+        }
+        let percent = (after as f64 - before as f64) * 100.0 / (before.max(1) as f64);
+        if after > before {
+            println!("Done: {before} bytes grew to {after} bytes (+{percent:.1}%)");
+        } else {
+            println!("Done: {before} bytes shrunk to {after} bytes ({percent:.1}%)");
+        }
     }
 
     Ok(())
 }
+
+fn run_validate(input: &Path) -> anyhow::Result<()> {
+    let report = stepreduce::validate(&read_input(input)?);
+    for issue in &report.issues {
+        println!("{issue}");
+    }
+    if !report.is_ok() {
+        bail!(
+            "{} has {} integrity issue(s)",
+            input.display(),
+            report.issues.len()
+        );
+    }
+    println!("{}: OK", input.display());
+    Ok(())
+}
+
+/// Print a distribution with power-of-two buckets (0, 1, 2–3, 4–7, …).
+fn print_distribution(name: &str, dist: &Distribution) {
+    println!("{name}: mean {:.2}, max {}", dist.mean(), dist.max());
+    let mut buckets: Vec<(usize, usize, usize)> = Vec::new();
+    for (&degree, &count) in &dist.histogram {
+        let lower = if degree == 0 { 0 } else { 1 << degree.ilog2() };
+        match buckets.last_mut() {
+            Some((l, _, c)) if *l == lower => *c += count,
+            _ => buckets.push((lower, (lower * 2).max(1) - 1, count)),
+        }
+    }
+    for (lower, upper, count) in buckets {
+        let range = if lower == upper {
+            lower.to_string()
+        } else {
+            format!("{lower}-{upper}")
+        };
+        println!("  {range:>11}  {count:>10}");
+    }
+}
+
+fn run_stats(input: &Path, options: &ReduceOptions) -> anyhow::Result<()> {
+    let stats = stepreduce::stats(&read_input(input)?, options)?;
+
+    let mut types: Vec<_> = stats.types.iter().collect();
+    types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+
+    let width = types.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    println!(
+        "{:width$}  {:>10}  {:>12}  {:>10}  {:>11}",
+        "TYPE", "COUNT", "BYTES", "DUPLICATE", "UNREACHABLE"
+    );
+    for (name, t) in &types {
+        println!(
+            "{name:width$}  {:>10}  {:>12}  {:>9.1}%  {:>11}",
+            t.count,
+            t.bytes,
+            t.duplicate_ratio() * 100.0,
+            t.unreachable
+        );
+    }
+    println!();
+    println!(
+        "{} entities, {} bytes",
+        stats.entity_count(),
+        types.iter().map(|(_, t)| t.bytes).sum::<usize>()
+    );
+    println!();
+    print_distribution("Fan-in", &stats.fan_in);
+    print_distribution("Fan-out", &stats.fan_out);
+
+    Ok(())
+}
+
+fn run_explain(input: &Path, id: u32, options: &ReduceOptions) -> anyhow::Result<()> {
+    let explanation = stepreduce::explain(&read_input(input)?, id, options)?;
+    if explanation.entity_type.is_none() {
+        bail!("{} has no entity #{id}", input.display());
+    }
+    println!("{explanation}");
+    Ok(())
+}
+
+fn run_graph(input: &Path, options: &GraphOptions, output: Option<&Path>) -> anyhow::Result<()> {
+    let graph = stepreduce::export_graph(&read_input(input)?, options)?;
+    match output {
+        Some(path) => {
+            fs::write(path, graph).with_context(|| format!("failed to write {}", path.display()))
+        }
+        None => {
+            print!("{graph}");
+            Ok(())
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Validate { input }) => run_validate(&input),
+        Some(Command::Stats { input, options }) => run_stats(&input, &options.to_options(&input)?),
+        Some(Command::Explain { input, id, options }) => {
+            run_explain(&input, id, &options.to_options(&input)?)
+        }
+        Some(Command::Graph {
+            input,
+            format,
+            from_ids,
+            from_types,
+            output,
+            root_types,
+            config,
+        }) => {
+            let ReduceOptions {
+                mut root_entities,
+                limits,
+                ..
+            } = config.load(&input)?;
+            extend_types(&mut root_entities, &root_types);
+            let options = GraphOptions {
+                format,
+                from_ids,
+                from_types,
+                root_entities,
+                limits,
+            };
+            run_graph(&input, &options, output.as_deref())
+        }
+        None => run_reduce(cli.reduce),
+    }
+}
@@ -0,0 +1,1758 @@
+//! A typed document model of STEP (ISO 10303-21) files.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::{self, Write},
+    ops::Range,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+#[cfg(feature = "mmap")]
+use std::{fs::File, path::Path};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use crate::{
+    Error,
+    limits::{LimitError, Limits},
+    parse::{self, Sections},
+    progress::{Monitor, Phase},
+    references,
+};
+
+/// Input shared by the entities parsed from it, which refer to their text
+/// in it instead of copying it.
+type SharedInput = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+/// A parsed STEP file: its header and the entity instances of its `DATA`
+/// section.
+///
+/// Parsing keeps the original text, and [`write`](Self::write) reproduces it
+/// for everything that was not modified: the header (if it still equals the
+/// parsed one), each entity parsed from the file (unless changed through
+/// [`Entity::records_mut`]) and everything after the `DATA` section. The
+/// rest is written in a compact canonical form. Continuation lines are
+/// always joined, so every entity is written on one line.
+#[derive(Debug, Clone, Default)]
+pub struct StepFile {
+    /// The header section.
+    pub header: Header,
+    /// The entity instances of the `DATA` section, in file order.
+    pub entities: Vec<Entity>,
+    layout: Option<Layout>,
+}
+
+/// The original text around the entities of a parsed file.
+#[derive(Debug, Clone)]
+struct Layout {
+    /// The header as parsed, to detect modifications.
+    header: Header,
+    /// The lines from the start of the file through `DATA;`.
+    header_lines: Vec<String>,
+    /// The lines from the `ENDSEC;` that closes the `DATA` section onward.
+    footer_lines: Vec<String>,
+}
+
+impl StepFile {
+    /// A new file with the given header and entities.
+    pub fn new(header: Header, entities: Vec<Entity>) -> Self {
+        Self {
+            header,
+            entities,
+            layout: None,
+        }
+    }
+
+    /// Parse STEP file content.
+    ///
+    /// Fails if the `DATA` section holds anything but entity instances
+    /// `#<id>=…;` with balanced parentheses and terminated strings. Within
+    /// those, parameters that are not valid Part 21 (such as `&SCOPE` or
+    /// integers beyond the `i64` range) are kept as [`Parameter::Raw`]. The
+    /// header is parsed leniently: header entities that cannot be parsed
+    /// are skipped (but kept by [`write`](Self::write) as long as the header
+    /// is not modified). Invalid UTF-8 is replaced with U+FFFD.
+    pub fn parse(input: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_input(input, None, &Limits::default(), &Monitor::default()).map_err(|e| match e
+        {
+            Error::Parse(e) => e,
+            _ => unreachable!("no limits and no cancellation token"),
+        })
+    }
+
+    /// Like [`parse`](Self::parse), but fail with [`Error::Limit`] as soon as
+    /// the input exceeds one of `limits`.
+    pub fn parse_with_limits(input: &[u8], limits: &Limits) -> Result<Self, Error> {
+        Self::parse_monitored(input, limits, &Monitor::default())
+    }
+
+    /// [`parse_with_limits`](Self::parse_with_limits), reporting progress
+    /// to and checking for cancellation with `monitor`.
+    pub(crate) fn parse_monitored(
+        input: &[u8],
+        limits: &Limits,
+        monitor: &Monitor,
+    ) -> Result<Self, Error> {
+        Self::parse_input(input, None, limits, monitor)
+    }
+
+    /// Like [`parse_with_limits`](Self::parse_with_limits), but memory-map
+    /// the file at `path` instead of reading it into memory. Entities refer
+    /// to their original text in the map rather than copying it, which keeps
+    /// the map alive as long as they do. Use
+    /// [`parse_with_limits`](Self::parse_with_limits) on the read file for
+    /// files that other processes may write to.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified while the returned file or any entity
+    /// taken from it is alive. Truncating it makes reading the text crash the
+    /// process (`SIGBUS`), and changing its content in place is undefined
+    /// behaviour, since the text is only checked to be UTF-8 while parsing.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn parse_file(path: impl AsRef<Path>, limits: &Limits) -> Result<Self, Error> {
+        // SAFETY: passed on to the caller.
+        unsafe { Self::parse_file_monitored(path.as_ref(), limits, &Monitor::default()) }
+    }
+
+    /// [`parse_file`](Self::parse_file), reporting progress to and checking
+    /// for cancellation with `monitor`.
+    ///
+    /// # Safety
+    ///
+    /// See [`parse_file`](Self::parse_file).
+    #[cfg(feature = "mmap")]
+    pub(crate) unsafe fn parse_file_monitored(
+        path: &Path,
+        limits: &Limits,
+        monitor: &Monitor,
+    ) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // Don't even map files over the size limit.
+        if let Some(limit) = limits.max_file_size {
+            let size = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+            if size > limit {
+                return Err(LimitError::FileSize { size, limit }.into());
+            }
+        }
+        // SAFETY: the caller guarantees that the file is not modified while
+        // it is mapped, which `Text::as_str` relies on as well.
+        let map = unsafe { Mmap::map(&file) }?;
+        let input: SharedInput = Arc::new(map);
+        Self::parse_input((*input).as_ref(), Some(&input), limits, monitor)
+    }
+
+    /// Parse `input`. If `shared` holds it, entities refer to their text in
+    /// it where possible.
+    fn parse_input(
+        input: &[u8],
+        shared: Option<&SharedInput>,
+        limits: &Limits,
+        monitor: &Monitor,
+    ) -> Result<Self, Error> {
+        if let Some(limit) = limits.max_file_size
+            && input.len() > limit
+        {
+            return Err(LimitError::FileSize {
+                size: input.len(),
+                limit,
+            }
+            .into());
+        }
+        monitor.report(Phase::Parse, 0.0);
+        let sections = parse::split_sections(input, limits)?;
+        let file = Self::from_sections(sections, shared.map(|shared| (shared, input)), monitor)?;
+        monitor.report(Phase::Parse, 1.0);
+        Ok(file)
+    }
+
+    fn from_sections(
+        sections: Sections<'_>,
+        shared: Option<(&SharedInput, &[u8])>,
+        monitor: &Monitor,
+    ) -> Result<Self, Error> {
+        let mut entities = Vec::with_capacity(sections.data.len());
+        let total = sections.data.len();
+        for (i, (data, &line)) in sections.data.iter().zip(&sections.lines).enumerate() {
+            monitor.step(Phase::Parse, i, total)?;
+            // Entries joined from several lines or with invalid UTF-8 are
+            // copies, the others are slices of the input.
+            let origin = match (data, shared) {
+                (Cow::Borrowed(text), Some((shared, input))) => {
+                    Some((shared, text.as_ptr().addr() - input.as_ptr().addr()))
+                }
+                _ => None,
+            };
+            parse_instances_in(data, origin, &mut entities)
+                .map_err(|message| ParseError { line, message })?;
+        }
+        let header = Header::from_lines(&sections.header);
+        Ok(Self {
+            header: header.clone(),
+            entities,
+            layout: Some(Layout {
+                header,
+                header_lines: sections.header,
+                footer_lines: sections.footer,
+            }),
+        })
+    }
+
+    /// Write the file as STEP, one line per entity.
+    pub fn write(&self, writer: impl Write) -> io::Result<()> {
+        self.write_monitored(writer, &Monitor::default())
+    }
+
+    /// [`write`](Self::write), reporting progress to `monitor`.
+    pub(crate) fn write_monitored(
+        &self,
+        mut writer: impl Write,
+        monitor: &Monitor,
+    ) -> io::Result<()> {
+        monitor.report(Phase::Write, 0.0);
+        match &self.layout {
+            Some(layout) if layout.header == self.header => {
+                for line in &layout.header_lines {
+                    writeln!(writer, "{line}")?;
+                }
+            }
+            _ => self.header.write(&mut writer)?,
+        }
+        for (i, entity) in self.entities.iter().enumerate() {
+            // Writing is not cancelled.
+            let _ = monitor.step(Phase::Write, i, self.entities.len());
+            writeln!(writer, "{entity}")?;
+        }
+        match &self.layout {
+            Some(layout) => {
+                for line in &layout.footer_lines {
+                    writeln!(writer, "{line}")?;
+                }
+            }
+            None => writeln!(writer, "ENDSEC;\nEND-ISO-10303-21;")?,
+        }
+        monitor.report(Phase::Write, 1.0);
+        Ok(())
+    }
+}
+
+/// The header section of a STEP file.
+///
+/// Strings are decoded, e.g. `'it''s'` is `it's`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// `FILE_DESCRIPTION.description`.
+    pub description: Vec<String>,
+    /// `FILE_DESCRIPTION.implementation_level`, `2;1` by default.
+    pub implementation_level: String,
+    /// `FILE_NAME.name`.
+    pub name: String,
+    /// `FILE_NAME.time_stamp`.
+    pub time_stamp: String,
+    /// `FILE_NAME.author`.
+    pub author: Vec<String>,
+    /// `FILE_NAME.organization`.
+    pub organization: Vec<String>,
+    /// `FILE_NAME.preprocessor_version`.
+    pub preprocessor_version: String,
+    /// `FILE_NAME.originating_system`.
+    pub originating_system: String,
+    /// `FILE_NAME.authorization`.
+    pub authorization: String,
+    /// `FILE_SCHEMA.schema_identifiers`.
+    pub schemas: Vec<String>,
+    /// All other header entities (e.g. `FILE_POPULATION`), in file order.
+    /// Also holds repeated or malformed `FILE_DESCRIPTION`, `FILE_NAME` and
+    /// `FILE_SCHEMA` entities.
+    pub extra: Vec<Record>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            description: Vec::new(),
+            implementation_level: "2;1".to_string(),
+            name: String::new(),
+            time_stamp: String::new(),
+            author: Vec::new(),
+            organization: Vec::new(),
+            preprocessor_version: String::new(),
+            originating_system: String::new(),
+            authorization: String::new(),
+            schemas: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+/// The strings of a list parameter, or `None` for any other parameter.
+fn string_list(parameter: &Parameter) -> Option<Vec<String>> {
+    let Parameter::List(items) = parameter else {
+        return None;
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Parameter::String(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn string_list_parameter(strings: &[String]) -> Parameter {
+    Parameter::List(strings.iter().cloned().map(Parameter::String).collect())
+}
+
+impl Header {
+    /// Parse the header section from the lines before the `DATA` section.
+    fn from_lines(lines: &[String]) -> Self {
+        let mut header = Self::default();
+        let (mut description, mut name, mut schema) = (false, false, false);
+        let text = lines.join("\n");
+        let mut in_header = false;
+
+        for statement in statements(&text) {
+            match statement {
+                "HEADER" => in_header = true,
+                "ENDSEC" => in_header = false,
+                _ if in_header => {
+                    let mut parser = Parser::new(statement);
+                    let Ok(record) = parser.record() else {
+                        continue;
+                    };
+                    if !parser.at_end() {
+                        continue;
+                    }
+                    let strings = |i: usize| match record.parameters.get(i) {
+                        Some(Parameter::String(s)) => Some(s.clone()),
+                        _ => None,
+                    };
+                    let lists = |i: usize| record.parameters.get(i).and_then(string_list);
+
+                    match record.type_name.as_str() {
+                        "FILE_DESCRIPTION" if !description && record.parameters.len() == 2 => {
+                            if let (Some(d), Some(level)) = (lists(0), strings(1)) {
+                                header.description = d;
+                                header.implementation_level = level;
+                                description = true;
+                                continue;
+                            }
+                        }
+                        "FILE_NAME" if !name && record.parameters.len() == 7 => {
+                            if let (
+                                Some(n),
+                                Some(time_stamp),
+                                Some(author),
+                                Some(organization),
+                                Some(preprocessor_version),
+                                Some(originating_system),
+                                Some(authorization),
+                            ) = (
+                                strings(0),
+                                strings(1),
+                                lists(2),
+                                lists(3),
+                                strings(4),
+                                strings(5),
+                                strings(6),
+                            ) {
+                                header.name = n;
+                                header.time_stamp = time_stamp;
+                                header.author = author;
+                                header.organization = organization;
+                                header.preprocessor_version = preprocessor_version;
+                                header.originating_system = originating_system;
+                                header.authorization = authorization;
+                                name = true;
+                                continue;
+                            }
+                        }
+                        "FILE_SCHEMA" if !schema && record.parameters.len() == 1 => {
+                            if let Some(schemas) = lists(0) {
+                                header.schemas = schemas;
+                                schema = true;
+                                continue;
+                            }
+                        }
+                        _ => {}
+                    }
+                    header.extra.push(record);
+                }
+                _ => {}
+            }
+        }
+        header
+    }
+
+    /// Write the header section in canonical form, from `ISO-10303-21;`
+    /// through `DATA;`.
+    fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let string = |s: &str| Parameter::String(s.to_string());
+        let records = [
+            Record::new(
+                "FILE_DESCRIPTION",
+                vec![
+                    string_list_parameter(&self.description),
+                    string(&self.implementation_level),
+                ],
+            ),
+            Record::new(
+                "FILE_NAME",
+                vec![
+                    string(&self.name),
+                    string(&self.time_stamp),
+                    string_list_parameter(&self.author),
+                    string_list_parameter(&self.organization),
+                    string(&self.preprocessor_version),
+                    string(&self.originating_system),
+                    string(&self.authorization),
+                ],
+            ),
+            Record::new("FILE_SCHEMA", vec![string_list_parameter(&self.schemas)]),
+        ];
+
+        writeln!(writer, "ISO-10303-21;\nHEADER;")?;
+        for record in records.iter().chain(&self.extra) {
+            writeln!(writer, "{record};")?;
+        }
+        writeln!(writer, "ENDSEC;\nDATA;")
+    }
+}
+
+/// Split `text` into `;`-terminated statements (trimmed, without the `;`),
+/// ignoring `;` in strings and comments.
+fn statements(text: &str) -> impl Iterator<Item = &str> {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\'' => {
+                    pos += 1;
+                    while pos < bytes.len() && bytes[pos] != b'\'' {
+                        pos += 1;
+                    }
+                }
+                b'/' if bytes.get(pos + 1) == Some(&b'*') => {
+                    pos = text[pos + 2..]
+                        .find("*/")
+                        .map_or(bytes.len(), |end| pos + end + 3);
+                    continue;
+                }
+                b';' => {
+                    let statement = text[start..pos].trim();
+                    pos += 1;
+                    start = pos;
+                    return Some(statement);
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        None
+    })
+}
+
+/// An entity instance `#<id>=…;` of the `DATA` section.
+///
+/// A simple instance has a single [`Record`]; a complex instance like
+/// `#5=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));` has one per
+/// partial entity, in file order.
+///
+/// Parsed entities keep only their text, and parse their records again on
+/// first access, so that files can be processed as text without holding
+/// every parameter in memory.
+#[derive(Debug, Clone)]
+pub struct Entity {
+    id: u32,
+    /// Set unless `text` is, in which case the records are parsed from it
+    /// on first access.
+    records: OnceLock<Vec<Record>>,
+    complex: bool,
+    /// The original right-hand side (without the `;`), if unmodified.
+    text: Option<Text>,
+    /// Whether `text` has a [`Parameter::Raw`].
+    raw: bool,
+}
+
+/// The original text of an entity.
+#[derive(Clone)]
+enum Text {
+    Owned(String),
+    /// A range of the input it was parsed from, which was valid UTF-8 then.
+    Shared(SharedInput, Range<usize>),
+}
+
+impl Text {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Owned(text) => text,
+            // SAFETY: the range was checked to be UTF-8 when the entity was
+            // parsed, and the input is not modified (which the callers of
+            // `parse_file` guarantee for memory-mapped files).
+            Self::Shared(input, range) => unsafe {
+                std::str::from_utf8_unchecked(&(**input).as_ref()[range.clone()])
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Entity {
+    /// A simple instance `#<id>=<type_name>(<parameters>);`.
+    pub fn new(id: u32, type_name: impl Into<String>, parameters: Vec<Parameter>) -> Self {
+        Self {
+            id,
+            records: OnceLock::from(vec![Record::new(type_name, parameters)]),
+            complex: false,
+            text: None,
+            raw: false,
+        }
+    }
+
+    /// A complex instance `#<id>=(<records>);`.
+    pub fn complex(id: u32, records: Vec<Record>) -> Self {
+        Self {
+            id,
+            records: OnceLock::from(records),
+            complex: true,
+            text: None,
+            raw: false,
+        }
+    }
+
+    /// The instance ID.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Change the instance ID. References to this entity are not updated.
+    pub fn set_id(&mut self, id: u32) {
+        self.id = id;
+    }
+
+    /// Whether this is a complex instance.
+    pub fn is_complex(&self) -> bool {
+        self.complex
+    }
+
+    /// The type of a simple instance, or of the first partial entity of a
+    /// complex one.
+    pub fn type_name(&self) -> &str {
+        self.records().first().map_or("", |r| &r.type_name)
+    }
+
+    /// The parameters of a simple instance, or of the first partial entity
+    /// of a complex one.
+    pub fn parameters(&self) -> &[Parameter] {
+        self.records().first().map_or(&[], |r| &r.parameters)
+    }
+
+    /// The records: one for a simple instance, one per partial entity for a
+    /// complex one.
+    pub fn records(&self) -> &[Record] {
+        self.records.get_or_init(|| {
+            let text = self.text.as_ref().expect("an entity has records or text");
+            Parser::new(text.as_str())
+                .body()
+                .expect("the text of a parsed entity should still parse")
+                .0
+        })
+    }
+
+    /// Mutable access to the records. The entity is written in canonical
+    /// form afterwards, even if nothing was changed.
+    pub fn records_mut(&mut self) -> &mut Vec<Record> {
+        self.records();
+        self.text = None;
+        self.records.get_mut().expect("parsed above")
+    }
+
+    /// Replace every reference `#<old>` with `#<new>` for each `old → new`
+    /// in `map`. References missing from `map` are left alone. The original
+    /// text is kept, with the references replaced.
+    pub fn remap_references(&mut self, map: &HashMap<u32, u32>) {
+        fn remap(parameter: &mut Parameter, map: &HashMap<u32, u32>) {
+            match parameter {
+                Parameter::Ref(id) => *id = map.get(id).copied().unwrap_or(*id),
+                Parameter::List(items) => items.iter_mut().for_each(|p| remap(p, map)),
+                Parameter::Typed { value, .. } => remap(value, map),
+                Parameter::Raw(text) => *text = references::remap_references(text, map),
+                _ => {}
+            }
+        }
+        if let Some(text) = &self.text {
+            // The text only needs rewriting (and copying out of a shared
+            // input) if one of its references changed; the records are parsed
+            // from the new text again.
+            let text = text.as_str();
+            let changed = references::find_references(text).any(|m| {
+                text[m.start + 1..m.end]
+                    .parse()
+                    .is_ok_and(|id: u32| map.get(&id).is_some_and(|&new| new != id))
+            });
+            if changed {
+                self.text = Some(Text::Owned(references::remap_references(text, map)));
+                self.records = OnceLock::new();
+            }
+        } else if let Some(records) = self.records.get_mut() {
+            for record in records {
+                record.parameters.iter_mut().for_each(|p| remap(p, map));
+            }
+        }
+    }
+
+    /// The right-hand side of the instance (without the `;`), as it is
+    /// written.
+    pub(crate) fn body(&self) -> Cow<'_, str> {
+        match &self.text {
+            Some(text) => Cow::Borrowed(text.as_str()),
+            None => {
+                let mut body = String::new();
+                self.write_body(&mut body)
+                    .expect("writing to a String should not fail");
+                Cow::Owned(body)
+            }
+        }
+    }
+
+    fn write_body(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        if let Some(text) = &self.text {
+            f.write_str(text.as_str())
+        } else if self.complex || self.records().len() != 1 {
+            f.write_char('(')?;
+            for record in self.records() {
+                write!(f, "{record}")?;
+            }
+            f.write_char(')')
+        } else {
+            write!(f, "{}", self.records()[0])
+        }
+    }
+
+    /// Whether a parameter (possibly nested) is a [`Parameter::Raw`].
+    pub(crate) fn has_raw_parameters(&self) -> bool {
+        fn is_raw(parameter: &Parameter) -> bool {
+            match parameter {
+                Parameter::Raw(_) => true,
+                Parameter::List(items) => items.iter().any(is_raw),
+                Parameter::Typed { value, .. } => is_raw(value),
+                _ => false,
+            }
+        }
+        match self.text {
+            Some(_) => self.raw,
+            None => self
+                .records()
+                .iter()
+                .flat_map(|r| &r.parameters)
+                .any(is_raw),
+        }
+    }
+
+    /// All referenced instance IDs, in order of appearance (with
+    /// duplicates).
+    pub fn references(&self) -> Vec<u32> {
+        fn collect(parameter: &Parameter, refs: &mut Vec<u32>) {
+            match parameter {
+                Parameter::Ref(id) => refs.push(*id),
+                Parameter::List(items) => items.iter().for_each(|p| collect(p, refs)),
+                Parameter::Typed { value, .. } => collect(value, refs),
+                Parameter::Raw(text) => refs.extend(
+                    references::find_references(text)
+                        .filter_map(|m| text[m.start + 1..m.end].parse::<u32>().ok()),
+                ),
+                _ => {}
+            }
+        }
+        // The text has the same references as the records, and is quicker to
+        // scan than parsing them.
+        if let Some(text) = &self.text {
+            let text = text.as_str();
+            return references::find_references(text)
+                .filter_map(|m| text[m.start + 1..m.end].parse().ok())
+                .collect();
+        }
+        let mut refs = Vec::new();
+        for record in self.records() {
+            record.parameters.iter().for_each(|p| collect(p, &mut refs));
+        }
+        refs
+    }
+}
+
+/// Equality of IDs and content, regardless of the original text.
+impl PartialEq for Entity {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.complex == other.complex && self.records() == other.records()
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}=", self.id)?;
+        self.write_body(f)?;
+        f.write_char(';')
+    }
+}
+
+/// Parse a single entity instance like `#1=CARTESIAN_POINT('',(0.,0.,0.));`.
+impl FromStr for Entity {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entities = Vec::new();
+        parse_instances(s, &mut entities).map_err(|message| ParseError { line: 1, message })?;
+        match <[Entity; 1]>::try_from(entities) {
+            Ok([entity]) => Ok(entity),
+            Err(entities) => Err(ParseError {
+                line: 1,
+                message: format!("expected one entity instance, found {}", entities.len()),
+            }),
+        }
+    }
+}
+
+/// An entity type with its parameters: a simple instance, a partial entity
+/// of a complex instance, or a header entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The entity type name as written, e.g. `CARTESIAN_POINT`.
+    pub type_name: String,
+    /// The parameters, in order.
+    pub parameters: Vec<Parameter>,
+}
+
+impl Record {
+    /// A record of `type_name` with `parameters`.
+    pub fn new(type_name: impl Into<String>, parameters: Vec<Parameter>) -> Self {
+        Self {
+            type_name: type_name.into(),
+            parameters,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.type_name)?;
+        write_list(f, &self.parameters)?;
+        f.write_char(')')
+    }
+}
+
+/// A parameter of a [`Record`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parameter {
+    /// A real literal like `1.5E-3`.
+    Real(Real),
+    /// An integer literal like `42`.
+    Integer(i64),
+    /// A string literal, decoded: `'it''s'` is `it's` and `'\X2\00E9\X0\'`
+    /// is `é`.
+    String(String),
+    /// An enumeration value like `.T.` or `.MILLI.`, without the dots.
+    Enum(String),
+    /// An entity instance reference like `#12`.
+    Ref(u32),
+    /// An aggregate like `(1.,2.,3.)`.
+    List(Vec<Parameter>),
+    /// An omitted optional attribute: `$`.
+    Omitted,
+    /// An attribute derived in a subtype: `*`.
+    Derived,
+    /// A typed value like `LENGTH_MEASURE(2.5)`.
+    Typed {
+        type_name: String,
+        value: Box<Parameter>,
+    },
+    /// A binary literal like `"0FF"`: hexadecimal digits, the first one
+    /// counting the unused bits.
+    Binary(String),
+    /// A parameter that is not valid Part 21, such as an integer beyond the
+    /// `i64` range or `&SCOPE`, kept as written so that it round-trips.
+    /// `#<id>` in it (outside strings) still counts as a reference.
+    Raw(String),
+}
+
+/// The value of a real literal, and the literal itself if it was parsed.
+///
+/// Values compare equal regardless of how they were written, so `1.5` and
+/// `1.50E0` are equal. Literals beyond the `f64` range are infinite. There
+/// is no literal for NaN.
+#[derive(Debug, Clone)]
+pub struct Real {
+    value: f64,
+    literal: Option<Box<str>>,
+}
+
+impl Real {
+    /// A real that is written in the shortest form that reads back as
+    /// `value`, e.g. `1.`, `0.25` or `1.E-7`.
+    pub fn new(value: f64) -> Self {
+        Self {
+            value,
+            literal: None,
+        }
+    }
+
+    /// The value.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The literal as parsed, if any.
+    pub fn literal(&self) -> Option<&str> {
+        self.literal.as_deref()
+    }
+}
+
+impl From<f64> for Real {
+    fn from(value: f64) -> Self {
+        Self::new(value)
+    }
+}
+
+impl PartialEq for Real {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl fmt::Display for Real {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.literal {
+            Some(literal) => f.write_str(literal),
+            None => write_real(f, self.value),
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Real(real) => write!(f, "{real}"),
+            Self::Integer(value) => write!(f, "{value}"),
+            Self::String(s) => write_string(f, s),
+            Self::Enum(name) => write!(f, ".{name}."),
+            Self::Ref(id) => write!(f, "#{id}"),
+            Self::List(items) => {
+                f.write_char('(')?;
+                write_list(f, items)?;
+                f.write_char(')')
+            }
+            Self::Omitted => f.write_char('$'),
+            Self::Derived => f.write_char('*'),
+            Self::Typed { type_name, value } => write!(f, "{type_name}({value})"),
+            Self::Binary(digits) => write!(f, "\"{digits}\""),
+            Self::Raw(text) => f.write_str(text),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, items: &[Parameter]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{item}")?;
+    }
+    Ok(())
+}
+
+/// Write `value` as the shortest real literal that reads back as `value`,
+/// e.g. `1.`, `0.25` or `1.E-7`. Infinities are written as `1.E999`.
+fn write_real(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        return write!(f, "{sign}1.E999");
+    }
+    let repr = format!("{value:?}");
+    match repr.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let dot = if mantissa.contains('.') { "" } else { "." };
+            write!(f, "{mantissa}{dot}E{exponent}")
+        }
+        None => f.write_str(
+            repr.strip_suffix(".0")
+                .map_or(&repr, |int| &repr[..int.len() + 1]),
+        ),
+    }
+}
+
+/// Write `s` as a string literal, encoding quotes, backslashes and
+/// everything outside printable ASCII.
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('\'')?;
+    let mut in_x2 = false;
+    for c in s.chars() {
+        if (' '..='~').contains(&c) {
+            if in_x2 {
+                f.write_str("\\X0\\")?;
+                in_x2 = false;
+            }
+            match c {
+                '\'' => f.write_str("''")?,
+                '\\' => f.write_str("\\\\")?,
+                _ => f.write_char(c)?,
+            }
+        } else if let Ok(unit) = u16::try_from(u32::from(c)) {
+            if !in_x2 {
+                f.write_str("\\X2\\")?;
+                in_x2 = true;
+            }
+            write!(f, "{unit:04X}")?;
+        } else {
+            if in_x2 {
+                f.write_str("\\X0\\")?;
+                in_x2 = false;
+            }
+            write!(f, "\\X4\\{:08X}\\X0\\", u32::from(c))?;
+        }
+    }
+    if in_x2 {
+        f.write_str("\\X0\\")?;
+    }
+    f.write_char('\'')
+}
+
+/// Decode the content of a string literal (between the quotes).
+///
+/// Supports `''`, `\\`, `\X\hh`, `\X2\…\X0\`, `\X4\…\X0\` and `\S\c` (with
+/// ISO 8859-1 as the only code page); `\P…\` directives are dropped.
+/// Anything else, including malformed directives, is kept as written.
+fn decode_string(raw: &str) -> String {
+    fn hex_units(s: &str, width: usize) -> (Vec<u32>, usize) {
+        let mut units = Vec::new();
+        let mut pos = 0;
+        while let Some(group) = s.get(pos..pos + width)
+            && let Ok(unit) = u32::from_str_radix(group, 16)
+            && group.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            units.push(unit);
+            pos += width;
+        }
+        (units, pos)
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(c) = rest.chars().next() {
+        if let Some(r) = rest.strip_prefix("''") {
+            out.push('\'');
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("\\\\") {
+            out.push('\\');
+            rest = r;
+        } else if let Some(r) = rest
+            .strip_prefix("\\X2\\")
+            .or_else(|| rest.strip_prefix("\\X4\\"))
+        {
+            let width = if rest.starts_with("\\X2\\") { 4 } else { 8 };
+            let (units, len) = hex_units(r, width);
+            let Some(r) = r[len..].strip_prefix("\\X0\\") else {
+                out.push('\\');
+                rest = &rest[1..];
+                continue;
+            };
+            if width == 4 {
+                out.extend(
+                    char::decode_utf16(units.into_iter().map(|u| u as u16))
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+            } else {
+                out.extend(
+                    units
+                        .into_iter()
+                        .map(|u| char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+            }
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("\\X\\")
+            && let (units, 2) = hex_units(r.get(..2).unwrap_or(""), 2)
+        {
+            out.extend(char::from_u32(units[0]));
+            rest = &r[2..];
+        } else if let Some(r) = rest.strip_prefix("\\S\\")
+            && let Some(b) = r.bytes().next().filter(u8::is_ascii)
+        {
+            out.extend(char::from_u32(u32::from(b) + 128));
+            rest = &r[1..];
+        } else if let Some(r) = rest.strip_prefix("\\P")
+            && r.as_bytes().get(1) == Some(&b'\\')
+            && r.as_bytes()[0].is_ascii_uppercase()
+        {
+            rest = &r[2..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// A malformed entity instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The 1-based line where the entity starts.
+    pub line: usize,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse the entity instances in one data section entry (usually exactly
+/// one, none for a comment) into `entities`.
+pub(crate) fn parse_instances(text: &str, entities: &mut Vec<Entity>) -> Result<(), String> {
+    parse_instances_in(text, None, entities)
+}
+
+/// Like [`parse_instances`]. If `text` starts at the given offset of a
+/// shared input, the entities refer to their text in it instead of copying
+/// it.
+fn parse_instances_in(
+    text: &str,
+    shared: Option<(&SharedInput, usize)>,
+    entities: &mut Vec<Entity>,
+) -> Result<(), String> {
+    let mut parser = Parser::new(text);
+    parser.shared = shared;
+    while !parser.at_end() {
+        entities.push(parser.instance()?);
+    }
+    Ok(())
+}
+
+/// A recursive-descent parser for the Part 21 exchange structure. Whitespace
+/// and comments may appear between all tokens.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// The shared input that `text` is a slice of, and its offset in it.
+    shared: Option<(&'a SharedInput, usize)>,
+    /// Whether a [`Parameter::Raw`] was parsed since the last instance.
+    raw: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            shared: None,
+            raw: false,
+        }
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        self.text.as_bytes()
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_space(&mut self) {
+        loop {
+            let rest = &self.text[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with("/*") {
+                return;
+            }
+            self.pos = trimmed[2..]
+                .find("*/")
+                .map_or(self.text.len(), |end| self.pos + end + 4);
+        }
+    }
+
+    /// The next byte after whitespace and comments.
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_space();
+        self.bytes().get(self.pos).copied()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    /// Describe what was found at the current position.
+    fn found(&self) -> String {
+        match self.text[self.pos..].chars().next() {
+            Some(c) => format!("`{c}` at column {}", self.pos + 1),
+            None => "end of input".to_string(),
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), String> {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected `{}`, found {}", b as char, self.found()))
+        }
+    }
+
+    /// Consume bytes matching `pred` and return them.
+    fn take_while(&mut self, pred: impl Fn(u8) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.bytes().get(self.pos).is_some_and(|&b| pred(b)) {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    /// `#<id>=<record or complex records>;`
+    fn instance(&mut self) -> Result<Entity, String> {
+        self.expect(b'#')?;
+        let digits = self.take_while(|b| b.is_ascii_digit());
+        let id = digits
+            .parse()
+            .map_err(|_| format!("invalid instance ID, found {}", self.found()))?;
+        self.expect(b'=')?;
+
+        self.skip_space();
+        let start = self.pos;
+        // The records are only parsed to check them here, and parsed again
+        // from the text when needed.
+        let (records, complex) = self.body()?;
+        if records.is_empty() {
+            return Err(format!("empty complex instance #{id}"));
+        }
+        let end = self.pos;
+        self.expect(b';')?;
+
+        Ok(Entity {
+            id,
+            records: OnceLock::new(),
+            complex,
+            raw: std::mem::take(&mut self.raw),
+            text: Some(match self.shared {
+                Some((input, offset)) => Text::Shared(input.clone(), offset + start..offset + end),
+                None => Text::Owned(self.text[start..end].to_string()),
+            }),
+        })
+    }
+
+    /// The right-hand side of an instance: a record, or complex records in
+    /// parentheses. Returns the records and whether they are complex.
+    fn body(&mut self) -> Result<(Vec<Record>, bool), String> {
+        if self.peek() != Some(b'(') {
+            return Ok((vec![self.record()?], false));
+        }
+        self.pos += 1;
+        let mut records = Vec::new();
+        while self.peek() != Some(b')') {
+            records.push(self.record()?);
+        }
+        self.pos += 1;
+        Ok((records, true))
+    }
+
+    /// A keyword such as an entity type name, optionally user-defined (`!`).
+    fn keyword(&mut self) -> Result<&'a str, String> {
+        self.skip_space();
+        let start = self.pos;
+        if self.bytes().get(self.pos) == Some(&b'!') {
+            self.pos += 1;
+        }
+        if !self
+            .bytes()
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_alphabetic() || *b == b'_')
+        {
+            self.pos = start;
+            return Err(format!("expected an entity type, found {}", self.found()));
+        }
+        self.take_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        Ok(&self.text[start..self.pos])
+    }
+
+    /// `<keyword>(<parameters>)`
+    fn record(&mut self) -> Result<Record, String> {
+        let type_name = self.keyword()?.to_string();
+        self.expect(b'(')?;
+        let parameters = self.list()?;
+        Ok(Record {
+            type_name,
+            parameters,
+        })
+    }
+
+    /// Comma-separated parameters up to and including the closing `)`.
+    fn list(&mut self) -> Result<Vec<Parameter>, String> {
+        let mut items = Vec::new();
+        if self.peek() == Some(b')') {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(self.parameter()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b')') => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                _ => return Err(format!("expected `,` or `)`, found {}", self.found())),
+            }
+        }
+    }
+
+    /// A parameter, or the text up to the next `,` or `)` as a
+    /// [`Parameter::Raw`] if it is not a valid one.
+    fn parameter(&mut self) -> Result<Parameter, String> {
+        self.skip_space();
+        let start = self.pos;
+        let error = match self.value() {
+            Ok(value) if matches!(self.peek(), Some(b',' | b')')) => return Ok(value),
+            Ok(_) => None,
+            Err(error) => Some(error),
+        };
+        self.pos = start;
+        // If there is no raw text either, why it is not a parameter is the
+        // more helpful error.
+        self.raw().map_err(|raw_error| error.unwrap_or(raw_error))
+    }
+
+    /// The text from the current position up to the next `,` or `)` outside
+    /// of parentheses and strings, or up to a `;`. Fails if it is empty or
+    /// runs to the end of the input.
+    fn raw(&mut self) -> Result<Parameter, String> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        loop {
+            match self.bytes().get(self.pos) {
+                None => return Err("expected `)`, found end of input".to_string()),
+                Some(b'\'') => {
+                    self.pos = references::string_end(self.bytes(), self.pos);
+                    continue;
+                }
+                Some(b'(') => depth += 1,
+                Some(b')') if depth > 0 => depth -= 1,
+                Some(b',' | b')') if depth == 0 => break,
+                Some(b';') => break,
+                Some(_) => {}
+            }
+            self.pos += 1;
+        }
+        let raw = self.text[start..self.pos].trim_end();
+        if raw.is_empty() {
+            return Err(format!("expected a parameter, found {}", self.found()));
+        }
+        self.raw = true;
+        Ok(Parameter::Raw(raw.to_string()))
+    }
+
+    /// A valid parameter.
+    fn value(&mut self) -> Result<Parameter, String> {
+        let Some(b) = self.peek() else {
+            return Err("expected a parameter, found end of input".to_string());
+        };
+        let next = self.bytes().get(self.pos + 1).copied();
+        match b {
+            b'$' => {
+                self.pos += 1;
+                Ok(Parameter::Omitted)
+            }
+            b'*' => {
+                self.pos += 1;
+                Ok(Parameter::Derived)
+            }
+            b'#' => {
+                self.pos += 1;
+                let digits = self.take_while(|b| b.is_ascii_digit());
+                digits
+                    .parse()
+                    .map(Parameter::Ref)
+                    .map_err(|_| format!("invalid reference, found {}", self.found()))
+            }
+            b'(' => {
+                self.pos += 1;
+                self.list().map(Parameter::List)
+            }
+            b'\'' => self.string().map(Parameter::String),
+            b'"' => {
+                self.pos += 1;
+                let digits = self.take_while(|b| b.is_ascii_hexdigit());
+                let digits = Parameter::Binary(digits.to_string());
+                self.expect(b'"').map(|()| digits)
+            }
+            b'.' if next.is_some_and(|b| b.is_ascii_alphabetic() || b == b'_') => {
+                self.pos += 1;
+                let name = self.take_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+                let name = Parameter::Enum(name.to_string());
+                self.expect(b'.').map(|()| name)
+            }
+            b'0'..=b'9' | b'+' | b'-' | b'.' => self.number(),
+            b'!' | b'_' | b'A'..=b'Z' | b'a'..=b'z' => {
+                let type_name = self.keyword()?.to_string();
+                self.expect(b'(')?;
+                let value = Box::new(self.value()?);
+                self.expect(b')')?;
+                Ok(Parameter::Typed { type_name, value })
+            }
+            _ => Err(format!("expected a parameter, found {}", self.found())),
+        }
+    }
+
+    /// A string literal; the opening quote is the next byte.
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.bytes().get(self.pos) {
+                None => return Err("unterminated string".to_string()),
+                Some(b'\'') if self.bytes().get(self.pos + 1) == Some(&b'\'') => self.pos += 2,
+                Some(b'\'') => break,
+                Some(_) => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        Ok(decode_string(&self.text[start..self.pos - 1]))
+    }
+
+    /// A real or integer literal.
+    fn number(&mut self) -> Result<Parameter, String> {
+        let start = self.pos;
+        if matches!(self.bytes()[self.pos], b'+' | b'-') {
+            self.pos += 1;
+        }
+        let mut digits = self.take_while(|b| b.is_ascii_digit()).len();
+        let mut real = false;
+        if self.bytes().get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            digits += self.take_while(|b| b.is_ascii_digit()).len();
+            real = true;
+        }
+        if digits == 0 {
+            self.pos = start;
+            return Err(format!("expected a number, found {}", self.found()));
+        }
+        if matches!(self.bytes().get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.bytes().get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if self.take_while(|b| b.is_ascii_digit()).is_empty() {
+                return Err(format!("expected exponent digits, found {}", self.found()));
+            }
+            real = true;
+        }
+
+        let literal = &self.text[start..self.pos];
+        if real {
+            literal
+                .parse()
+                .map(|value| {
+                    Parameter::Real(Real {
+                        value,
+                        literal: Some(literal.into()),
+                    })
+                })
+                .map_err(|_| format!("invalid real {literal}"))
+        } else {
+            literal
+                .parse()
+                .map(Parameter::Integer)
+                .map_err(|_| format!("integer {literal} out of range"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "\
+ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('a part'),'2;1');
+FILE_NAME('part.step','2024-01-01T00:00:00',('J. Doe'),(''),
+  'pp','sys','');
+FILE_SCHEMA(('AUTOMOTIVE_DESIGN'));
+ENDSEC;
+DATA;
+#1 = CARTESIAN_POINT( '', ( 0., 1.5E-3, -2. ) );
+#2=( LENGTH_UNIT() NAMED_UNIT( * ) SI_UNIT( .MILLI., .METRE. ) );
+#3=PRODUCT('it''s','\\X2\\00E9\\X0\\',$,(#1,#2));
+/* comment */
+#4=MEASURE_WITH_UNIT(LENGTH_MEASURE(25.4),#2);#5=FOO(\"0FF\",42);
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    fn parse(input: &str) -> StepFile {
+        StepFile::parse(input.as_bytes()).unwrap()
+    }
+
+    fn write(file: &StepFile) -> String {
+        let mut output = Vec::new();
+        file.write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn header() {
+            let header = parse(INPUT).header;
+            assert_eq!(header.description, ["a part"]);
+            assert_eq!(header.implementation_level, "2;1");
+            assert_eq!(header.name, "part.step");
+            assert_eq!(header.author, ["J. Doe"]);
+            assert_eq!(header.originating_system, "sys");
+            assert_eq!(header.schemas, ["AUTOMOTIVE_DESIGN"]);
+            assert!(header.extra.is_empty());
+        }
+
+        #[test]
+        fn entities() {
+            let file = parse(INPUT);
+            let ids: Vec<u32> = file.entities.iter().map(Entity::id).collect();
+            assert_eq!(ids, [1, 2, 3, 4, 5]);
+
+            let point = &file.entities[0];
+            assert_eq!(point.type_name(), "CARTESIAN_POINT");
+            assert_eq!(
+                point.parameters(),
+                [
+                    Parameter::String(String::new()),
+                    Parameter::List(vec![
+                        Parameter::Real(0.0.into()),
+                        Parameter::Real(1.5e-3.into()),
+                        Parameter::Real((-2.0).into())
+                    ]),
+                ]
+            );
+
+            let unit = &file.entities[1];
+            assert!(unit.is_complex());
+            let types: Vec<&str> = unit.records().iter().map(|r| &*r.type_name).collect();
+            assert_eq!(types, ["LENGTH_UNIT", "NAMED_UNIT", "SI_UNIT"]);
+            assert_eq!(unit.records()[1].parameters, [Parameter::Derived]);
+            assert_eq!(
+                unit.records()[2].parameters,
+                [
+                    Parameter::Enum("MILLI".to_string()),
+                    Parameter::Enum("METRE".to_string())
+                ]
+            );
+
+            let product = &file.entities[2];
+            assert_eq!(
+                product.parameters()[0],
+                Parameter::String("it's".to_string())
+            );
+            assert_eq!(product.parameters()[1], Parameter::String("é".to_string()));
+            assert_eq!(product.parameters()[2], Parameter::Omitted);
+            assert_eq!(product.references(), [1, 2]);
+
+            assert_eq!(
+                file.entities[3].parameters()[0],
+                Parameter::Typed {
+                    type_name: "LENGTH_MEASURE".to_string(),
+                    value: Box::new(Parameter::Real(25.4.into())),
+                }
+            );
+            assert_eq!(
+                file.entities[4].parameters(),
+                [Parameter::Binary("0FF".to_string()), Parameter::Integer(42)]
+            );
+        }
+
+        #[test]
+        fn errors() {
+            let cases = [
+                (
+                    "#1=FOO(;",
+                    "line 1: expected a parameter, found `;` at column 8",
+                ),
+                ("garbage;", "line 1: expected `#`, found `g` at column 1"),
+                (
+                    "#x=FOO();",
+                    "line 1: invalid instance ID, found `x` at column 2",
+                ),
+                ("#1=FOO('x);", "line 1: unterminated string"),
+                (
+                    "#1=FOO(1,,2);",
+                    "line 1: expected a parameter, found `,` at column 10",
+                ),
+                (
+                    "#1=FOO((1);",
+                    "line 1: expected `,` or `)`, found `;` at column 11",
+                ),
+                ("#1=();", "line 1: empty complex instance #1"),
+                ("#1=FOO()", "line 1: expected `;`, found end of input"),
+            ];
+            for (data, message) in cases {
+                let error = data.parse::<Entity>().unwrap_err();
+                assert_eq!(error.to_string(), message, "{data}");
+            }
+        }
+
+        #[test]
+        fn raw() {
+            let raw = |text: &str| Parameter::Raw(text.to_string());
+            let entity: Entity = "#1=FOO(99999999999999999999,&SCOPE,(1 2,#),1E,A(.B));"
+                .parse()
+                .unwrap();
+            assert_eq!(
+                entity.parameters(),
+                [
+                    raw("99999999999999999999"),
+                    raw("&SCOPE"),
+                    Parameter::List(vec![raw("1 2"), raw("#")]),
+                    raw("1E"),
+                    raw("A(.B)"),
+                ]
+            );
+            let mut canonical = entity.clone();
+            canonical.records_mut();
+            assert_eq!(canonical.to_string(), entity.to_string());
+
+            let mut entity: Entity = "#1=FOO(#2.5,'#2');".parse().unwrap();
+            assert_eq!(entity.references(), [2]);
+            entity.remap_references(&HashMap::from([(2, 3)]));
+            assert_eq!(
+                entity.parameters(),
+                [raw("#3.5"), Parameter::String("#2".into())]
+            );
+        }
+
+        #[test]
+        fn real_literal() {
+            let mut entity: Entity = "#1=FOO(1.50000E+00,2.);".parse().unwrap();
+            let Parameter::Real(real) = &entity.parameters()[0] else {
+                panic!("not a real");
+            };
+            assert_eq!(real.value(), 1.5);
+            assert_eq!(real.literal(), Some("1.50000E+00"));
+            assert_eq!(entity.parameters()[0], Parameter::Real(1.5.into()));
+
+            entity.records_mut()[0].parameters[1] = Parameter::Real(0.25.into());
+            assert_eq!(entity.to_string(), "#1=FOO(1.50000E+00,0.25);");
+        }
+
+        #[test]
+        fn error_line() {
+            let input = "DATA;\n#1=FOO();\n#2=BAR(\n1,,2);\nENDSEC;\n";
+            let error = StepFile::parse(input.as_bytes()).unwrap_err();
+            assert_eq!(error.line, 3);
+        }
+
+        #[test]
+        fn limits() {
+            let limits = Limits {
+                max_file_size: Some(10),
+                ..Default::default()
+            };
+            assert!(matches!(
+                StepFile::parse_with_limits(INPUT.as_bytes(), &limits),
+                Err(Error::Limit(LimitError::FileSize { .. }))
+            ));
+            assert!(matches!(
+                StepFile::parse_with_limits(b"DATA;\n#1=FOO(;\n", &Limits::default()),
+                Err(Error::Parse(_))
+            ));
+        }
+
+        #[cfg(feature = "mmap")]
+        #[test]
+        fn file() {
+            let path = std::env::temp_dir().join(format!("stepreduce-{}.step", std::process::id()));
+            std::fs::write(&path, INPUT).unwrap();
+            // SAFETY: the file is only removed, which keeps the map valid.
+            let mut file = unsafe { StepFile::parse_file(&path, &Limits::default()) }.unwrap();
+            let limited = unsafe {
+                StepFile::parse_file(
+                    &path,
+                    &Limits {
+                        max_file_size: Some(10),
+                        ..Default::default()
+                    },
+                )
+            };
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(file.entities, parse(INPUT).entities);
+            assert_eq!(write(&file), write(&parse(INPUT)));
+            assert!(matches!(file.entities[2].text, Some(Text::Shared(..))));
+            // Remapping copies the text out of the map only if it changes.
+            file.entities[2].remap_references(&HashMap::from([(99, 1)]));
+            assert!(matches!(file.entities[2].text, Some(Text::Shared(..))));
+            file.entities[2].remap_references(&HashMap::from([(2, 7)]));
+            assert!(matches!(file.entities[2].text, Some(Text::Owned(_))));
+            assert!(file.entities[2].to_string().contains("#7"));
+
+            assert!(matches!(
+                limited,
+                Err(Error::Limit(LimitError::FileSize { .. }))
+            ));
+            assert!(matches!(
+                unsafe { StepFile::parse_file(&path, &Limits::default()) },
+                Err(Error::Io(_))
+            ));
+        }
+    }
+
+    mod write {
+        use super::*;
+
+        #[test]
+        fn unmodified() {
+            let output = write(&parse(INPUT));
+            assert_eq!(
+                output,
+                "\
+ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('a part'),'2;1');
+FILE_NAME('part.step','2024-01-01T00:00:00',('J. Doe'),(''),
+  'pp','sys','');
+FILE_SCHEMA(('AUTOMOTIVE_DESIGN'));
+ENDSEC;
+DATA;
+#1=CARTESIAN_POINT( '', ( 0., 1.5E-3, -2. ) );
+#2=( LENGTH_UNIT() NAMED_UNIT( * ) SI_UNIT( .MILLI., .METRE. ) );
+#3=PRODUCT('it''s','\\X2\\00E9\\X0\\',$,(#1,#2));
+#4=MEASURE_WITH_UNIT(LENGTH_MEASURE(25.4),#2);
+#5=FOO(\"0FF\",42);
+ENDSEC;
+END-ISO-10303-21;
+"
+            );
+        }
+
+        #[test]
+        fn modified() {
+            let mut file = parse(INPUT);
+            file.header.name = "renamed.step".to_string();
+            file.entities[0].set_id(10);
+            file.entities[1].records_mut();
+            file.entities.truncate(3);
+
+            let output = write(&file);
+            assert_eq!(
+                output,
+                "\
+ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('a part'),'2;1');
+FILE_NAME('renamed.step','2024-01-01T00:00:00',('J. Doe'),(''),'pp','sys','');
+FILE_SCHEMA(('AUTOMOTIVE_DESIGN'));
+ENDSEC;
+DATA;
+#10=CARTESIAN_POINT( '', ( 0., 1.5E-3, -2. ) );
+#2=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));
+#3=PRODUCT('it''s','\\X2\\00E9\\X0\\',$,(#1,#2));
+ENDSEC;
+END-ISO-10303-21;
+"
+            );
+        }
+
+        #[test]
+        fn new_file() {
+            let header = Header {
+                schemas: vec!["CONFIG_CONTROL_DESIGN".to_string()],
+                ..Default::default()
+            };
+            let entities = vec![
+                Entity::new(
+                    1,
+                    "CARTESIAN_POINT",
+                    vec![
+                        Parameter::String("a 'b' \\ c ü 😀".to_string()),
+                        Parameter::List(vec![
+                            Parameter::Real(1.0.into()),
+                            Parameter::Real((-0.25).into()),
+                            Parameter::Real(1e-7.into()),
+                            Parameter::Real(1.5e20.into()),
+                            Parameter::Real(f64::NEG_INFINITY.into()),
+                        ]),
+                    ],
+                ),
+                Entity::complex(2, vec![Record::new("A", vec![]), Record::new("B", vec![])]),
+            ];
+            let file = StepFile::new(header, entities);
+
+            let output = write(&file);
+            assert_eq!(
+                output,
+                "\
+ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION((),'2;1');
+FILE_NAME('','',(),(),'','','');
+FILE_SCHEMA(('CONFIG_CONTROL_DESIGN'));
+ENDSEC;
+DATA;
+#1=CARTESIAN_POINT('a ''b'' \\\\ c \\X2\\00FC\\X0\\ \\X4\\0001F600\\X0\\',(1.,-0.25,1.E-7,1.5E20,-1.E999));
+#2=(A()B());
+ENDSEC;
+END-ISO-10303-21;
+"
+            );
+            assert_eq!(parse(&output).entities, file.entities);
+        }
+    }
+
+    mod remap_references {
+        use super::*;
+
+        #[test]
+        fn typed_and_text() {
+            let map = HashMap::from([(2, 5), (3, 6)]);
+            let mut entity: Entity = "#1 = FOO( 'a', #2, ( #3, #9 ) );".parse().unwrap();
+            entity.remap_references(&map);
+            assert_eq!(entity.references(), [5, 6, 9]);
+            assert_eq!(entity.to_string(), "#1=FOO( 'a', #5, ( #6, #9 ) );");
+
+            let mut entity = Entity::new(
+                1,
+                "FOO",
+                vec![Parameter::List(vec![Parameter::Typed {
+                    type_name: "BAR".into(),
+                    value: Box::new(Parameter::Ref(3)),
+                }])],
+            );
+            entity.remap_references(&map);
+            assert_eq!(entity.to_string(), "#1=FOO((BAR(#6)));");
+        }
+    }
+
+    mod decode_string {
+        use super::*;
+
+        #[test]
+        fn directives() {
+            assert_eq!(decode_string("a''b\\\\c"), "a'b\\c");
+            assert_eq!(decode_string("\\X\\E9t\\X\\e9"), "été");
+            assert_eq!(decode_string("\\S\\i\\PA\\x"), "éx");
+            assert_eq!(decode_string("\\X2\\03B103B2\\X0\\!"), "αβ!");
+            assert_eq!(decode_string("\\X4\\0001F600\\X0\\"), "😀");
+        }
+
+        #[test]
+        fn malformed() {
+            assert_eq!(decode_string("\\X2\\00E"), "\\X2\\00E");
+            assert_eq!(decode_string("\\X\\G1"), "\\X\\G1");
+            assert_eq!(decode_string("C:\\temp"), "C:\\temp");
+        }
+    }
+}
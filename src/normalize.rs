@@ -1,38 +1,50 @@
-use std::sync::LazyLock;
-
-use regex::Regex;
-
-use crate::find_numbers::find_numbers;
-
-/// Matches entity declarations like `PRODUCT('name'` and captures up to and
-/// including the opening quote.
-static NAME_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^([A-Z_]+\()'[^']*'").unwrap());
+use std::{
+    cmp::{self, Ordering},
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+};
+
+use crate::{find_numbers::find_numbers, parse::Instance, references::string_end};
+
+/// Exponents beyond this magnitude are not expanded, since expansion writes
+/// one digit per unit of exponent.
+const MAX_EXPANDED_EXPONENT: i64 = 4096;
+
+/// The decimal exponent of a number literal (`0` if it has none), saturated
+/// to the `i64` range.
+pub(crate) fn exponent(s: &str) -> i64 {
+    let Some(e_pos) = s.find(['e', 'E']) else {
+        return 0;
+    };
+    let digits = &s[e_pos + 1..];
+    digits.parse().unwrap_or(if digits.starts_with('-') {
+        i64::MIN
+    } else {
+        i64::MAX
+    })
+}
 
-/// Matches `UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(<value>)` and
-/// captures `<value>`.
-static UNCERTAINTY_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"(?i)UNCERTAINTY_MEASURE_WITH_UNIT\s*\(\s*LENGTH_MEASURE\s*\(\s*([^)]+)\s*\)")
-        .unwrap()
-});
+/// Whether `s` can be normalized; see [`normalize_number`].
+fn is_expandable(s: &str) -> bool {
+    exponent(s).unsigned_abs() <= MAX_EXPANDED_EXPONENT as u64
+}
 
 /// Normalize a single floating-point number string into a canonical form.
 ///
 /// Scientific notation is expanded, leading zeros on the integer part and
 /// trailing zeros on the fractional part are stripped, and the result always
-/// contains a decimal point.
+/// contains a decimal point. Literals with an exponent beyond
+/// `MAX_EXPANDED_EXPONENT` are returned unchanged.
 pub(crate) fn normalize_number(s: &str) -> String {
-    let mut exp_val: i32 = 0;
-    let mantissa_str;
-
-    // Split off exponent.
-    let upper = s.to_ascii_uppercase();
-    if let Some(e_pos) = upper.find('E') {
-        mantissa_str = &s[..e_pos];
-        exp_val = s[e_pos + 1..].parse::<i32>().unwrap_or(0);
-    } else {
-        mantissa_str = s;
+    if !is_expandable(s) {
+        return s.to_string();
     }
+    let exp_val = exponent(s) as i32;
+    let mantissa_str = match s.find(['e', 'E']) {
+        Some(e_pos) => &s[..e_pos],
+        None => s,
+    };
 
     let negative = mantissa_str.starts_with('-');
     let mantissa_str = if negative {
@@ -109,67 +121,284 @@ pub(crate) fn normalize_number(s: &str) -> String {
     }
 }
 
-/// Round a number string to at most `max_decimals` fractional digits, then
-/// normalize.
-pub(crate) fn round_number(s: &str, max_decimals: u32) -> String {
-    let normalized = normalize_number(s);
+/// How numbers are rounded before they are compared (and, when quantizing,
+/// written).
+///
+/// All modes truncate towards zero, so rounding an already rounded number
+/// again is a no-op.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// Keep at most this many fractional digits.
+    DecimalPlaces(u32),
+    /// Keep at most this many significant digits, whatever the magnitude.
+    /// Must be at least 1.
+    SignificantDigits(u32),
+    /// Round to a multiple of this positive tolerance, e.g. `1e-6`.
+    Absolute(f64),
+}
 
-    if !normalized.contains('.') {
-        return normalized;
+impl FromStr for Precision {
+    type Err = String;
+
+    /// Parse `N` (decimal places), `sig:N` (significant digits) or `tol:EPS`
+    /// (absolute tolerance).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid precision '{s}' (expected N, sig:N or tol:EPS)");
+        if let Some(digits) = s.strip_prefix("sig:") {
+            // No significant digits would round every number to zero.
+            match digits.parse() {
+                Ok(0) | Err(_) => Err(invalid()),
+                Ok(n) => Ok(Self::SignificantDigits(n)),
+            }
+        } else if let Some(eps) = s.strip_prefix("tol:") {
+            match eps.parse::<f64>() {
+                Ok(eps) if eps > 0.0 && eps.is_finite() => Ok(Self::Absolute(eps)),
+                _ => Err(invalid()),
+            }
+        } else {
+            s.parse().map(Self::DecimalPlaces).map_err(|_| invalid())
+        }
     }
+}
 
-    let negative = normalized.starts_with('-');
-    let body = if negative {
-        &normalized[1..]
-    } else {
-        &normalized
-    };
-
-    let dot = body.find('.').unwrap();
-    let int_part = &body[..dot];
-    let frac_part = &body[dot + 1..];
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DecimalPlaces(n) => write!(f, "{n} decimal places"),
+            Self::SignificantDigits(n) => write!(f, "{n} significant digits"),
+            Self::Absolute(eps) => write!(f, "multiples of {eps}"),
+        }
+    }
+}
 
-    let frac_part = if frac_part.len() > max_decimals as usize {
-        &frac_part[..max_decimals as usize]
-    } else {
-        frac_part
+/// Split a normalized number into sign, integer and fractional digits.
+fn split_normalized(normalized: &str) -> (bool, &str, &str) {
+    let (negative, body) = match normalized.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, normalized),
     };
+    let (int_part, frac_part) = body.split_once('.').unwrap_or((body, ""));
+    (negative, int_part, frac_part)
+}
 
-    // Strip trailing zeros.
-    let last_nonzero = frac_part.rfind(|c: char| c != '0');
-    let frac_part = match last_nonzero {
-        None => "",
-        Some(pos) => &frac_part[..=pos],
+/// Join sign, integer and fractional digits into a normalized number.
+fn join_normalized(negative: bool, int_part: &str, frac_part: &str) -> String {
+    let int_part = match int_part.trim_start_matches('0') {
+        "" => "0",
+        trimmed => trimmed,
     };
+    let frac_part = frac_part.trim_end_matches('0');
 
     if int_part == "0" && frac_part.is_empty() {
         return "0.".to_string();
     }
 
-    let result = format!("{int_part}.{frac_part}");
-    if negative {
-        format!("-{result}")
-    } else {
-        result
+    let sign = if negative { "-" } else { "" };
+    format!("{sign}{int_part}.{frac_part}")
+}
+
+/// Round a number string according to `precision`, then normalize.
+pub(crate) fn round_number(s: &str, precision: Precision) -> String {
+    if !is_expandable(s) {
+        return s.to_string();
+    }
+    let normalized = normalize_number(s);
+    let (negative, int_part, frac_part) = split_normalized(&normalized);
+
+    match precision {
+        Precision::DecimalPlaces(n) => {
+            let frac_part = &frac_part[..frac_part.len().min(n as usize)];
+            join_normalized(negative, int_part, frac_part)
+        }
+        Precision::SignificantDigits(n) => {
+            let n = n as usize;
+            if int_part != "0" {
+                if int_part.len() >= n {
+                    let zeros = "0".repeat(int_part.len() - n);
+                    join_normalized(negative, &format!("{}{zeros}", &int_part[..n]), "")
+                } else {
+                    let keep = frac_part.len().min(n - int_part.len());
+                    join_normalized(negative, int_part, &frac_part[..keep])
+                }
+            } else {
+                let zeros = frac_part.len() - frac_part.trim_start_matches('0').len();
+                let keep = frac_part.len().min(zeros + n);
+                join_normalized(negative, int_part, &frac_part[..keep])
+            }
+        }
+        Precision::Absolute(eps) => {
+            let Ok(value) = normalized.parse::<f64>() else {
+                return normalized;
+            };
+            // Nudge the quotient away from zero so that multiples of `eps`
+            // that are not exactly representable stay put.
+            let multiple = (value / eps * (1.0 + 4.0 * f64::EPSILON)).trunc();
+            if eps <= 0.0 || !multiple.is_finite() {
+                return normalized;
+            }
+            let decimals = eps
+                .to_string()
+                .split_once('.')
+                .map_or(0, |(_, frac)| frac.len());
+            normalize_number(&format!("{:.decimals$}", multiple * eps))
+        }
+    }
+}
+
+/// Compare the magnitudes of two normalized numbers.
+fn cmp_magnitude(a: &str, b: &str) -> Ordering {
+    let (_, a_int, a_frac) = split_normalized(a);
+    let (_, b_int, b_frac) = split_normalized(b);
+    a_int
+        .len()
+        .cmp(&b_int.len())
+        .then_with(|| a_int.cmp(b_int))
+        .then_with(|| a_frac.cmp(b_frac))
+}
+
+/// The precision for each number in a line, resolved by entity type and
+/// attribute position.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PrecisionPolicy {
+    /// Precision for numbers without an override.
+    pub default: Option<Precision>,
+    /// Overrides by entity type.
+    pub types: HashMap<String, Precision>,
+    /// Overrides by entity type and zero-based attribute position; these win
+    /// over `types`.
+    pub attributes: HashMap<String, HashMap<usize, Precision>>,
+    /// Precision by original entity ID, replacing `default` for entities in a
+    /// representation context with its own uncertainty.
+    pub entities: HashMap<u32, Precision>,
+}
+
+impl PrecisionPolicy {
+    /// Whether no number is ever rounded.
+    pub fn is_exact(&self) -> bool {
+        self.default.is_none()
+            && self.types.is_empty()
+            && self.attributes.is_empty()
+            && self.entities.is_empty()
+    }
+
+    /// The precision for numbers of entity `id` without a type override.
+    pub fn base(&self, id: u32) -> Option<Precision> {
+        self.entities.get(&id).copied().or(self.default)
+    }
+
+    fn resolve(
+        &self,
+        entity_type: &str,
+        attribute: usize,
+        base: Option<Precision>,
+    ) -> Option<Precision> {
+        self.attributes
+            .get(entity_type)
+            .and_then(|positions| positions.get(&attribute))
+            .or_else(|| self.types.get(entity_type))
+            .copied()
+            .or(base)
+    }
+
+    /// Split `rhs` of entity `id` into runs of equal precision, as
+    /// `(start, precision)` pairs in increasing order of `start`. The first
+    /// run starts at 0.
+    ///
+    /// Each partial of a complex instance is resolved by its own type.
+    /// Numbers nested in lists or typed parameters belong to the enclosing
+    /// attribute.
+    fn runs(&self, rhs: &str, id: u32) -> Vec<(usize, Option<Precision>)> {
+        let base = self.base(id);
+        let mut runs = vec![(0, base)];
+        if self.types.is_empty() && self.attributes.is_empty() {
+            return runs;
+        }
+
+        let bytes = rhs.as_bytes();
+        let mut depth = 0;
+        let mut in_string = false;
+        // Depth of the current entity's argument list, its type and the
+        // current attribute position.
+        let mut entity: Option<(usize, &str, usize)> = None;
+        let mut ident_start = 0;
+
+        for (i, &b) in bytes.iter().enumerate() {
+            match b {
+                b'\'' => in_string = !in_string,
+                _ if in_string => {}
+                b'(' => {
+                    depth += 1;
+                    let ident = rhs[ident_start..i].trim();
+                    if entity.is_none() && !ident.is_empty() {
+                        entity = Some((depth, ident, 0));
+                        runs.push((i, self.resolve(ident, 0, base)));
+                    }
+                }
+                b')' => {
+                    if entity.is_some_and(|(d, _, _)| d == depth) {
+                        entity = None;
+                        runs.push((i, base));
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                b',' => {
+                    if let Some((d, ident, attribute)) = &mut entity
+                        && *d == depth
+                    {
+                        *attribute += 1;
+                        runs.push((i, self.resolve(ident, *attribute, base)));
+                    }
+                }
+                _ => {}
+            }
+            if !(b.is_ascii_alphanumeric() || b == b'_') {
+                ident_start = i + 1;
+            }
+        }
+
+        runs
+    }
+}
+
+/// Looks up the precision of numbers at increasing positions of a line.
+struct RunCursor {
+    runs: Vec<(usize, Option<Precision>)>,
+    index: usize,
+}
+
+impl RunCursor {
+    fn new(policy: &PrecisionPolicy, rhs: &str, id: u32) -> Self {
+        Self {
+            runs: policy.runs(rhs, id),
+            index: 0,
+        }
+    }
+
+    fn at(&mut self, pos: usize) -> Option<Precision> {
+        while self.index + 1 < self.runs.len() && self.runs[self.index + 1].0 <= pos {
+            self.index += 1;
+        }
+        self.runs[self.index].1
     }
 }
 
 /// Replace all floating-point numbers in `rhs` with their normalized (and
 /// optionally rounded) forms.
 ///
-/// If `max_decimals` is `Some(n)`, numbers are rounded to `n` decimal places.
-/// If `None`, numbers are only normalized (scientific notation expanded, zeros
-/// stripped).
-pub(crate) fn normalize_numbers_in_line(rhs: &str, max_decimals: Option<u32>) -> String {
+/// Numbers are rounded to the precision `policy` resolves for them in entity
+/// `id` (an original ID), or only normalized (scientific notation expanded,
+/// zeros stripped) if it resolves none.
+pub(crate) fn normalize_numbers_in_line(rhs: &str, policy: &PrecisionPolicy, id: u32) -> String {
     let mut result = String::with_capacity(rhs.len());
     let mut last_pos = 0;
+    let mut cursor = RunCursor::new(policy, rhs, id);
 
     for m in find_numbers(rhs) {
         result.push_str(&rhs[last_pos..m.start]);
 
         let num_str = &rhs[m.start..m.end];
-        let replacement = match max_decimals {
-            Some(n) => round_number(num_str, n),
+        let replacement = match cursor.at(m.start) {
+            Some(p) => round_number(num_str, p),
             None => normalize_number(num_str),
         };
         result.push_str(&replacement);
@@ -181,16 +410,184 @@ pub(crate) fn normalize_numbers_in_line(rhs: &str, max_decimals: Option<u32>) ->
     result
 }
 
+/// Rewrite a REAL literal to the shortest Part 21 form with the same decimal
+/// value: either positional (`0.0000001`, `1.`) or scientific (`1.E-7`).
+///
+/// Literals without a decimal point are not Part 21 REALs and are returned
+/// unchanged, as is the original text if no form is shorter.
+pub(crate) fn shorten_number(s: &str) -> String {
+    if !s.contains('.') || !is_expandable(s) {
+        return s.to_string();
+    }
+
+    let plain = normalize_number(s);
+    let (sign, body) = match plain.strip_prefix('-') {
+        Some(body) => ("-", body),
+        None => ("", plain.as_str()),
+    };
+    let (int_part, frac_part) = body.split_once('.').unwrap();
+
+    // Significant digits and the decimal exponent of the first one.
+    let (digits, exponent) = if int_part != "0" {
+        (format!("{int_part}{frac_part}"), int_part.len() as i64 - 1)
+    } else {
+        let zeros = frac_part.len() - frac_part.trim_start_matches('0').len();
+        (frac_part[zeros..].to_string(), -(zeros as i64) - 1)
+    };
+    let digits = digits.trim_end_matches('0');
+
+    let mut candidates = vec![s.to_string(), plain.clone()];
+    if !digits.is_empty() {
+        let (first, rest) = digits.split_at(1);
+        candidates.push(format!("{sign}{first}.{rest}E{exponent}"));
+    }
+    candidates.into_iter().min_by_key(String::len).unwrap()
+}
+
+/// Replace every number literal outside string literals in `rhs` with
+/// `rewrite(position, literal)`.
+pub(crate) fn rewrite_numbers_in_line(
+    rhs: &str,
+    mut rewrite: impl FnMut(usize, &str) -> String,
+) -> String {
+    let mut result = String::with_capacity(rhs.len());
+    let mut in_string = false;
+    let mut offset = 0;
+
+    // Quotes toggle string mode; an escaped quote (`''`) toggles it twice.
+    for (i, segment) in rhs.split('\'').enumerate() {
+        if i > 0 {
+            result.push('\'');
+            in_string = !in_string;
+        }
+        let start = offset;
+        offset += segment.len() + 1;
+        if in_string {
+            result.push_str(segment);
+            continue;
+        }
+        let mut last_pos = 0;
+        for m in find_numbers(segment) {
+            result.push_str(&segment[last_pos..m.start]);
+            result.push_str(&rewrite(start + m.start, &segment[m.start..m.end]));
+            last_pos = m.end;
+        }
+        result.push_str(&segment[last_pos..]);
+    }
+
+    result
+}
+
+/// Replace all REAL literals outside string literals in `rhs` with their
+/// shortest equivalent form (see [`shorten_number`]).
+pub(crate) fn shorten_numbers_in_line(rhs: &str) -> String {
+    rewrite_numbers_in_line(rhs, |_, s| shorten_number(s))
+}
+
+/// Replace all REAL literals outside string literals in `rhs` with their
+/// values rounded to the precision `policy` resolves for them in entity `id`
+/// (an original ID; see [`round_number`]), but keeping at least
+/// `min_decimals` fractional digits where the number has them. The rounded
+/// values are written in their shortest form (see [`shorten_number`]), so
+/// that `1.E-9` does not grow into `0.000000001`.
+pub(crate) fn quantize_numbers_in_line(
+    rhs: &str,
+    policy: &PrecisionPolicy,
+    id: u32,
+    min_decimals: Option<u32>,
+) -> String {
+    let mut cursor = RunCursor::new(policy, rhs, id);
+    rewrite_numbers_in_line(rhs, |pos, s| {
+        let Some(precision) = cursor.at(pos).filter(|_| s.contains('.')) else {
+            return s.to_string();
+        };
+        let rounded = round_number(s, precision);
+        let rounded = match min_decimals {
+            Some(n) => {
+                let guarded = round_number(s, Precision::DecimalPlaces(n));
+                cmp::max_by(rounded, guarded, |a, b| cmp_magnitude(a, b))
+            }
+            None => rounded,
+        };
+        shorten_number(&rounded)
+    })
+}
+
+/// Split entity declarations like `PRODUCT('name'…` into the type with the
+/// opening parenthesis (`PRODUCT(`), the name up to the next quote, and the
+/// rest after that quote. `None` if the entity does not start with a string
+/// attribute (or its type has characters other than `[A-Z_]`).
+fn split_entity_name(rhs: &str) -> Option<(&str, &str, &str)> {
+    let bytes = rhs.as_bytes();
+    let type_len = bytes
+        .iter()
+        .take_while(|&&b| b.is_ascii_uppercase() || b == b'_')
+        .count();
+    if type_len == 0 || bytes.get(type_len..type_len + 2) != Some(b"('") {
+        return None;
+    }
+    let name_start = type_len + 2;
+    let name_end = name_start + bytes[name_start..].iter().position(|&b| b == b'\'')?;
+    Some((
+        &rhs[..type_len + 1],
+        &rhs[name_start..name_end],
+        &rhs[name_end + 1..],
+    ))
+}
+
 /// Strip the quoted name from entity declarations like `PRODUCT('name'…`
 /// by replacing the name with an empty string.
 pub(crate) fn normalize_entity_name(rhs: &str) -> String {
-    if let Some(caps) = NAME_PATTERN.captures(rhs) {
-        let full = caps.get(0).unwrap();
-        let prefix = &caps[1]; // e.g. "PRODUCT("
-        format!("{}''{}", prefix, &rhs[full.end()..])
-    } else {
-        rhs.to_string()
+    match split_entity_name(rhs) {
+        Some((prefix, _, rest)) => format!("{prefix}''{rest}"),
+        None => rhs.to_string(),
+    }
+}
+
+/// Return the quoted name of entity declarations like `PRODUCT('name'…`,
+/// or `None` if the entity does not start with a string attribute.
+pub(crate) fn entity_name(rhs: &str) -> Option<&str> {
+    split_entity_name(rhs).map(|(_, name, _)| name)
+}
+
+/// The `<value>` of the first
+/// `UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(<value>)` in `line`, outside
+/// of string literals. Keywords are matched case-insensitively, and
+/// whitespace is allowed around the parentheses.
+pub(crate) fn uncertainty_value(line: &str) -> Option<&str> {
+    const KEYWORD: &[u8] = b"UNCERTAINTY_MEASURE_WITH_UNIT";
+
+    /// `(LENGTH_MEASURE(<value>)` at the start of `rest`.
+    fn length_measure(rest: &str) -> Option<&str> {
+        const KEYWORD: &str = "LENGTH_MEASURE";
+        let rest = rest.trim_start().strip_prefix('(')?.trim_start();
+        let (keyword, rest) = rest.split_at_checked(KEYWORD.len())?;
+        if !keyword.eq_ignore_ascii_case(KEYWORD) {
+            return None;
+        }
+        let rest = rest.trim_start().strip_prefix('(')?;
+        let value = &rest[..rest.find(')')?];
+        (!value.is_empty()).then_some(value)
+    }
+
+    let bytes = line.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'\'' {
+            pos = string_end(bytes, pos);
+        } else if bytes[pos..]
+            .get(..KEYWORD.len())
+            .is_some_and(|word| word.eq_ignore_ascii_case(KEYWORD))
+        {
+            pos += KEYWORD.len();
+            if let Some(value) = length_measure(&line[pos..]) {
+                return Some(value);
+            }
+        } else {
+            pos += 1;
+        }
     }
+    None
 }
 
 /// Derive the number of significant decimal places from the STEP file's
@@ -198,10 +595,10 @@ pub(crate) fn normalize_entity_name(rhs: &str) -> String {
 ///
 /// Returns `Some(n)` where `n` is `ceil(-log10(value)) + 1`, or `None` if
 /// no valid uncertainty is found.
-pub(crate) fn extract_uncertainty(data_lines: &[String]) -> Option<u32> {
-    for line in data_lines {
-        if let Some(caps) = UNCERTAINTY_PATTERN.captures(line)
-            && let Ok(val) = caps[1].trim().parse::<f64>()
+pub(crate) fn extract_uncertainty(data_lines: &[impl Instance]) -> Option<u32> {
+    for (_, rhs) in data_lines.iter().filter_map(Instance::instance) {
+        if let Some(value) = uncertainty_value(rhs)
+            && let Ok(val) = value.trim().parse::<f64>()
             && val > 0.0
         {
             return Some((-val.log10()).ceil() as u32 + 1);
@@ -230,6 +627,20 @@ mod tests {
             assert_eq!(normalize_number("2.5e+2"), "250.");
         }
 
+        #[test]
+        fn huge_exponent() {
+            assert_eq!(normalize_number("1.E999999999"), "1.E999999999");
+            assert_eq!(
+                normalize_number("1.E-99999999999999999999"),
+                "1.E-99999999999999999999"
+            );
+            assert_eq!(
+                round_number("1.5E999999999", Precision::DecimalPlaces(2)),
+                "1.5E999999999"
+            );
+            assert_eq!(shorten_number("1.50E999999999"), "1.50E999999999");
+        }
+
         #[test]
         fn leading_dot() {
             assert_eq!(normalize_number(".5"), "0.5");
@@ -251,13 +662,48 @@ mod tests {
 
         #[test]
         fn truncation() {
-            assert_eq!(round_number("3.14159", 3), "3.141");
-            assert_eq!(round_number("3.14159", 0), "3.");
+            assert_eq!(
+                round_number("3.14159", Precision::DecimalPlaces(3)),
+                "3.141"
+            );
+            assert_eq!(round_number("3.14159", Precision::DecimalPlaces(0)), "3.");
         }
 
         #[test]
         fn shorter_than_limit() {
-            assert_eq!(round_number("3.14", 5), "3.14");
+            assert_eq!(round_number("3.14", Precision::DecimalPlaces(5)), "3.14");
+        }
+
+        #[test]
+        fn significant_digits() {
+            let p = Precision::SignificantDigits(3);
+            assert_eq!(round_number("1.23456E-05", p), "0.0000123");
+            assert_eq!(round_number("-123456.7", p), "-123000.");
+            assert_eq!(round_number("12.3456", p), "12.3");
+            assert_eq!(round_number("0.", p), "0.");
+        }
+
+        #[test]
+        fn absolute() {
+            let p = Precision::Absolute(0.005);
+            assert_eq!(round_number("1.23456", p), "1.23");
+            assert_eq!(round_number("-1.2375", p), "-1.235");
+            assert_eq!(round_number("0.3", p), "0.3");
+            assert_eq!(round_number("0.001", p), "0.");
+        }
+    }
+
+    mod precision {
+        use super::*;
+
+        #[test]
+        fn from_str() {
+            assert_eq!("3".parse(), Ok(Precision::DecimalPlaces(3)));
+            assert_eq!("sig:6".parse(), Ok(Precision::SignificantDigits(6)));
+            assert!("sig:0".parse::<Precision>().is_err());
+            assert_eq!("tol:1e-6".parse(), Ok(Precision::Absolute(1e-6)));
+            assert!("tol:-1".parse::<Precision>().is_err());
+            assert!("abc".parse::<Precision>().is_err());
         }
     }
 
@@ -267,16 +713,192 @@ mod tests {
         #[test]
         fn basic() {
             let input = "CARTESIAN_POINT('',-1.200E+1,3.0,0.00)";
-            let result = normalize_numbers_in_line(input, None);
+            let result = normalize_numbers_in_line(input, &PrecisionPolicy::default(), 1);
             assert_eq!(result, "CARTESIAN_POINT('',-12.,3.,0.)");
         }
 
         #[test]
         fn with_rounding() {
             let input = "CARTESIAN_POINT('',1.23456,7.89012)";
-            let result = normalize_numbers_in_line(input, Some(3));
+            let result = normalize_numbers_in_line(
+                input,
+                &PrecisionPolicy {
+                    default: Some(Precision::DecimalPlaces(3)),
+                    ..Default::default()
+                },
+                1,
+            );
             assert_eq!(result, "CARTESIAN_POINT('',1.234,7.89)");
         }
+
+        fn policy() -> PrecisionPolicy {
+            PrecisionPolicy {
+                default: Some(Precision::DecimalPlaces(1)),
+                types: HashMap::from([
+                    ("CARTESIAN_POINT".to_string(), Precision::DecimalPlaces(3)),
+                    (
+                        "RATIONAL_B_SPLINE_CURVE".to_string(),
+                        Precision::SignificantDigits(2),
+                    ),
+                ]),
+                attributes: HashMap::from([(
+                    "CIRCLE".to_string(),
+                    HashMap::from([(2, Precision::DecimalPlaces(2))]),
+                )]),
+                entities: HashMap::from([(7, Precision::DecimalPlaces(4))]),
+            }
+        }
+
+        #[test]
+        fn per_entity() {
+            let input = "DIRECTION('',(0.98765,0.))";
+            assert_eq!(
+                normalize_numbers_in_line(input, &policy(), 7),
+                "DIRECTION('',(0.9876,0.))"
+            );
+            let input = "CARTESIAN_POINT('',(1.23456,0.))";
+            assert_eq!(
+                normalize_numbers_in_line(input, &policy(), 7),
+                "CARTESIAN_POINT('',(1.234,0.))"
+            );
+        }
+
+        #[test]
+        fn per_type() {
+            let result = normalize_numbers_in_line(
+                "CARTESIAN_POINT('1.5',(1.23456,2.34567,3.45678))",
+                &policy(),
+                1,
+            );
+            assert_eq!(result, "CARTESIAN_POINT('1.5',(1.234,2.345,3.456))");
+            let result = normalize_numbers_in_line("DIRECTION('',(0.98765,0.))", &policy(), 1);
+            assert_eq!(result, "DIRECTION('',(0.9,0.))");
+        }
+
+        #[test]
+        fn per_attribute() {
+            let result = normalize_numbers_in_line(
+                "CIRCLE('',0.12345,LENGTH_MEASURE(12.34567))",
+                &policy(),
+                1,
+            );
+            assert_eq!(result, "CIRCLE('',0.1,LENGTH_MEASURE(12.34))");
+        }
+
+        #[test]
+        fn complex_partials() {
+            let result = normalize_numbers_in_line(
+                "(BOUNDED_CURVE() RATIONAL_B_SPLINE_CURVE((1.23456,0.0098765)) \
+                 B_SPLINE_CURVE(2,(1.23456)))",
+                &policy(),
+                1,
+            );
+            assert_eq!(
+                result,
+                "(BOUNDED_CURVE() RATIONAL_B_SPLINE_CURVE((1.2,0.0098)) \
+                 B_SPLINE_CURVE(2,(1.2)))"
+            );
+        }
+    }
+
+    mod shorten_number {
+        use super::*;
+
+        #[test]
+        fn exporter_output() {
+            assert_eq!(shorten_number("1.00000000000000E+000"), "1.");
+            assert_eq!(shorten_number("-0.000000000000000"), "0.");
+            assert_eq!(shorten_number("2.50000000000000E-001"), "0.25");
+        }
+
+        #[test]
+        fn scientific_when_shorter() {
+            assert_eq!(shorten_number("0.0000001"), "1.E-7");
+            assert_eq!(shorten_number("-1.5E+12"), "-1.5E12");
+            assert_eq!(shorten_number("123000."), "1.23E5");
+            assert_eq!(shorten_number("1230.0"), "1230.");
+        }
+
+        #[test]
+        fn keeps_original_when_shortest() {
+            assert_eq!(shorten_number("1.E-7"), "1.E-7");
+            assert_eq!(shorten_number("2E5"), "2E5");
+        }
+
+        #[test]
+        fn same_value() {
+            for s in ["1.00000000000000E+000", "0.0000001", "-3.1400E2", "123.456"] {
+                assert_eq!(normalize_number(&shorten_number(s)), normalize_number(s));
+            }
+        }
+    }
+
+    mod shorten_numbers_in_line {
+        use super::*;
+
+        #[test]
+        fn skips_strings() {
+            let input = "CARTESIAN_POINT('1.000 it''s 2.0',1.0000E+00,-0.000)";
+            assert_eq!(
+                shorten_numbers_in_line(input),
+                "CARTESIAN_POINT('1.000 it''s 2.0',1.,0.)"
+            );
+        }
+    }
+
+    mod quantize_numbers_in_line {
+        use super::*;
+
+        #[test]
+        fn rounds_reals_only() {
+            let input = "CARTESIAN_POINT('1.23456',1.23456E+01,-0.00001,2E5)";
+            assert_eq!(
+                quantize_numbers_in_line(
+                    input,
+                    &PrecisionPolicy {
+                        default: Some(Precision::DecimalPlaces(2)),
+                        ..Default::default()
+                    },
+                    1,
+                    None
+                ),
+                "CARTESIAN_POINT('1.23456',12.34,0.,2E5)"
+            );
+        }
+
+        #[test]
+        fn keeps_min_decimals() {
+            let input = "CARTESIAN_POINT('',1234.5678,0.0012345)";
+            assert_eq!(
+                quantize_numbers_in_line(
+                    input,
+                    &PrecisionPolicy {
+                        default: Some(Precision::SignificantDigits(2)),
+                        ..Default::default()
+                    },
+                    1,
+                    Some(3)
+                ),
+                "CARTESIAN_POINT('',1234.567,0.0012)"
+            );
+        }
+
+        #[test]
+        fn shortest_form() {
+            let input = "CARTESIAN_POINT('',1.E-9,1.E2,1.23456789E-9)";
+            assert_eq!(
+                quantize_numbers_in_line(
+                    input,
+                    &PrecisionPolicy {
+                        default: Some(Precision::DecimalPlaces(12)),
+                        ..Default::default()
+                    },
+                    1,
+                    None
+                ),
+                "CARTESIAN_POINT('',1.E-9,100.,1.234E-9)"
+            );
+        }
     }
 
     mod normalize_entity_name {
@@ -297,19 +919,65 @@ mod tests {
         }
     }
 
+    mod entity_name {
+        use super::*;
+
+        #[test]
+        fn quoted() {
+            assert_eq!(entity_name("PRODUCT('My Part',extra)"), Some("My Part"));
+            assert_eq!(entity_name("DIRECTION('',1.,0.,0.)"), Some(""));
+        }
+
+        #[test]
+        fn none() {
+            assert_eq!(entity_name("VERTEX_POINT(#1)"), None);
+            assert_eq!(entity_name("PRODUCT('unclosed"), None);
+            assert_eq!(entity_name("B2_CURVE('x')"), None);
+            assert_eq!(entity_name("('x')"), None);
+        }
+    }
+
     mod extract_uncertainty {
         use super::*;
 
         #[test]
         fn found() {
-            let lines = vec!["UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(0.001))".to_string()];
+            let lines = vec!["#1=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(0.001))".to_string()];
             assert_eq!(extract_uncertainty(&lines), Some(4));
         }
 
         #[test]
         fn not_found() {
-            let lines = vec!["CARTESIAN_POINT('',0.,0.,0.)".to_string()];
+            let lines = vec!["#1=CARTESIAN_POINT('',0.,0.,0.)".to_string()];
             assert_eq!(extract_uncertainty(&lines), None);
         }
+
+        #[test]
+        fn spacing_and_case() {
+            assert_eq!(
+                uncertainty_value(
+                    "#7=uncertainty_measure_with_unit ( Length_Measure( 1.E-5 ) ,#2)"
+                ),
+                Some(" 1.E-5 ")
+            );
+            assert_eq!(
+                uncertainty_value(
+                    "(UNCERTAINTY_MEASURE_WITH_UNIT(#1)UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(2.)))"
+                ),
+                Some("2.")
+            );
+        }
+
+        #[test]
+        fn strings() {
+            assert_eq!(
+                uncertainty_value("X('UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(1.))')"),
+                None
+            );
+            assert_eq!(
+                uncertainty_value("UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE())"),
+                None
+            );
+        }
     }
 }
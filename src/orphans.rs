@@ -1,14 +1,16 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::{
-    deduplicate::get_entity_type,
-    references::{collect_references, remap_references},
+    deduplicate::get_entity_types,
+    parse::Instance,
+    progress::{Cancelled, Monitor, Phase},
+    references::collect_references,
 };
 
 /// STEP entity types that serve as GC roots. Any entity reachable from one of
 /// these (transitively via `#NNN` references) is kept; everything else is
 /// removed.
-const GC_ROOT_ENTITIES: &[&str] = &[
+pub const GC_ROOT_ENTITIES: &[&str] = &[
     "APPLICATION_CONTEXT",
     "APPLICATION_PROTOCOL_DEFINITION",
     "CONTEXT_DEPENDENT_SHAPE_REPRESENTATION",
@@ -20,125 +22,338 @@ const GC_ROOT_ENTITIES: &[&str] = &[
     "SHAPE_REPRESENTATION_RELATIONSHIP",
 ];
 
-/// Remove unreachable ("orphan") entities from the data section.
+/// Entity types that annotate other entities (colours, properties,
+/// descriptions) and are commonly worth keeping when what they annotate is
+/// kept. Used by the `--keep-annotations` CLI flag.
+pub const DEFAULT_ANNOTATION_ENTITIES: &[&str] = &[
+    "STYLED_ITEM",
+    "OVER_RIDING_STYLED_ITEM",
+    "PROPERTY_DEFINITION",
+    "PROPERTY_DEFINITION_REPRESENTATION",
+    "DESCRIPTIVE_REPRESENTATION_ITEM",
+];
+
+/// Return `true` if an instance with these (partial) types is a GC root,
+/// being in [`GC_ROOT_ENTITIES`] or `extra`.
+pub(crate) fn is_gc_root(entity_types: &[&str], extra: &[String]) -> bool {
+    entity_types
+        .iter()
+        .any(|t| GC_ROOT_ENTITIES.contains(t) || extra.iter().any(|e| e == t))
+}
+
+/// Walk forward references from all GC roots in `lines`, which are the
+/// entities of a type in [`GC_ROOT_ENTITIES`] or `roots`.
 ///
-/// Starting from entities whose types are in [`GC_ROOT_ENTITIES`], a
-/// forward-reference walk marks all transitively reachable entities. Entities
-/// not reached are dropped, and surviving entities are renumbered starting
-/// from 1.
+/// Afterwards, unreachable entities whose type is listed in `annotations` are
+/// kept as well if they annotate live data: every reference they make must
+/// be live, or point to dead data that only the annotation owns (such as its
+/// style chain), which is kept with it. Owned data must not reference the
+/// live set itself, and must only be referenced by the annotation, by other
+/// owned data or by other annotations. Dead data attached to live data (such
+/// as an orphaned face) is never revived. Kept annotations extend the live
+/// set for the ones that follow.
 ///
-/// If no GC roots are found (e.g. the file has an unusual structure), all
-/// lines are returned unchanged.
-pub(crate) fn remove_orphans(lines: &[String]) -> Vec<String> {
+/// Returns every reachable entity ID mapped to the entity it was first
+/// reached from (`None` for roots and kept annotations). The walk is
+/// breadth-first from the roots in the order of `lines`, so following these
+/// links gives a shortest path from the first such root. The map is empty if
+/// the file contains no GC roots.
+pub(crate) fn reachable_from_roots(
+    lines: &[impl Instance],
+    roots: &[String],
+    annotations: &[String],
+) -> HashMap<u32, Option<u32>> {
+    walk_from_roots(lines, roots, annotations, &Monitor::default())
+        .unwrap_or_else(|Cancelled| unreachable!("no cancellation token"))
+}
+
+/// [`reachable_from_roots`], reporting progress to and checking for
+/// cancellation with `monitor`.
+fn walk_from_roots(
+    lines: &[impl Instance],
+    roots: &[String],
+    annotations: &[String],
+    monitor: &Monitor,
+) -> Result<HashMap<u32, Option<u32>>, Cancelled> {
     let mut id_to_rhs: HashMap<u32, &str> = HashMap::new();
-    let mut id_to_refs: HashMap<u32, HashSet<u32>> = HashMap::new();
+    // References in ascending order, so that the walk does not depend on the
+    // order of a hash set.
+    let mut id_to_refs: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut order: Vec<u32> = Vec::with_capacity(lines.len());
 
-    for line in lines {
-        let Some(eq) = line.find('=') else {
+    // Indexing is the first part of the progress, the walk the second and
+    // the backward walk for annotations (if any) the third.
+    let total = if annotations.is_empty() { 2 } else { 3 } * lines.len();
+    for (i, line) in lines.iter().enumerate() {
+        monitor.step(Phase::RemoveOrphans, i, total)?;
+        let Some((eid, rhs)) = line.instance() else {
             continue;
         };
-        let eid: u32 = match line[1..eq].trim().parse() {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-        let rhs = &line[eq + 1..];
+        let mut refs: Vec<u32> = collect_references(rhs).into_iter().collect();
+        refs.sort_unstable();
         id_to_rhs.insert(eid, rhs);
-        id_to_refs.insert(eid, collect_references(rhs));
+        id_to_refs.insert(eid, refs);
+        order.push(eid);
     }
 
-    // Seed the reachable set from GC root entity types.
-    let mut reachable: HashSet<u32> = HashSet::new();
-    let mut stack: Vec<u32> = Vec::new();
+    // Seed the reachable set from GC root entity types, in file order.
+    let mut reachable: HashMap<u32, Option<u32>> = HashMap::new();
+    let mut queue: VecDeque<u32> = VecDeque::new();
 
-    for (&eid, rhs) in &id_to_rhs {
-        let etype = get_entity_type(rhs);
-        if GC_ROOT_ENTITIES.contains(&etype) {
-            stack.push(eid);
-            reachable.insert(eid);
+    for &eid in &order {
+        if !reachable.contains_key(&eid) && is_gc_root(&get_entity_types(id_to_rhs[&eid]), roots) {
+            queue.push_back(eid);
+            reachable.insert(eid, None);
         }
     }
 
     // Walk forward references.
-    while let Some(eid) = stack.pop() {
+    let mut visited = lines.len();
+    while let Some(eid) = queue.pop_front() {
+        monitor.step(Phase::RemoveOrphans, visited, total)?;
+        visited += 1;
         if let Some(refs) = id_to_refs.get(&eid) {
             for &r in refs {
-                if !reachable.contains(&r) && id_to_rhs.contains_key(&r) {
-                    reachable.insert(r);
-                    stack.push(r);
+                if !reachable.contains_key(&r) && id_to_rhs.contains_key(&r) {
+                    reachable.insert(r, Some(eid));
+                    queue.push_back(r);
                 }
             }
         }
     }
 
-    if reachable.is_empty() {
-        return lines.to_vec();
+    if !reachable.is_empty() && !annotations.is_empty() {
+        keep_annotations(
+            &order,
+            &id_to_rhs,
+            &id_to_refs,
+            annotations,
+            &mut reachable,
+            monitor,
+            (visited, total),
+        )?;
     }
+    monitor.report(Phase::RemoveOrphans, 1.0);
 
-    // Rebuild with only reachable entities, renumbered.
-    let mut renumber: HashMap<u32, u32> = HashMap::new();
-    let mut surviving: Vec<(u32, &str)> = Vec::new();
-
-    for line in lines {
-        let Some(eq) = line.find('=') else {
-            continue;
-        };
-        let eid: u32 = match line[1..eq].trim().parse() {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
+    Ok(reachable)
+}
 
-        if reachable.contains(&eid) {
-            let new_id = surviving.len() as u32 + 1;
-            renumber.insert(eid, new_id);
-            surviving.push((new_id, &line[eq + 1..]));
+/// Add annotation entities (and the data they exclusively own) to
+/// `reachable`, see [`reachable_from_roots`].
+///
+/// Only direct referrers of live entities can qualify, so rather than
+/// testing every candidate against the live set until nothing changes, this
+/// checks the referrers of each live entity once it is live. Kept
+/// annotations and their data join the live set and continue the walk.
+/// Every live entity is visited once, counting from `done` of `total` for
+/// progress.
+fn keep_annotations(
+    order: &[u32],
+    id_to_rhs: &HashMap<u32, &str>,
+    id_to_refs: &HashMap<u32, Vec<u32>>,
+    annotations: &[String],
+    reachable: &mut HashMap<u32, Option<u32>>,
+    monitor: &Monitor,
+    (mut done, total): (usize, usize),
+) -> Result<(), Cancelled> {
+    // Referrers in file order, like the roots of the walk.
+    let mut referrers: HashMap<u32, Vec<u32>> = HashMap::new();
+    for eid in order {
+        for &r in id_to_refs.get(eid).into_iter().flatten() {
+            referrers.entry(r).or_default().push(*eid);
         }
     }
 
-    surviving
+    let is_candidate: HashSet<u32> = id_to_rhs
         .iter()
-        .map(|(new_id, rhs)| {
-            let remapped = remap_references(rhs, &renumber);
-            format!("#{new_id}={remapped}")
+        .filter(|(_, rhs)| {
+            get_entity_types(rhs)
+                .iter()
+                .any(|t| annotations.iter().any(|a| a == t))
         })
+        .map(|(&eid, _)| eid)
+        .collect();
+
+    let mut live: Vec<u32> = reachable.keys().copied().collect();
+    live.sort_unstable_by(|a, b| b.cmp(a));
+
+    while let Some(eid) = live.pop() {
+        monitor.step(Phase::RemoveOrphans, done, total)?;
+        done += 1;
+        for &candidate in referrers.get(&eid).into_iter().flatten() {
+            if !is_candidate.contains(&candidate) || reachable.contains_key(&candidate) {
+                continue;
+            }
+            let Some(owned) =
+                owned_data(candidate, id_to_refs, &referrers, &is_candidate, reachable)
+            else {
+                continue;
+            };
+            reachable.insert(candidate, None);
+            live.push(candidate);
+            let mut owned_ids: Vec<u32> = owned.keys().copied().collect();
+            owned_ids.sort_unstable_by(|a, b| b.cmp(a));
+            live.extend(owned_ids);
+            reachable.extend(owned);
+        }
+    }
+    Ok(())
+}
+
+/// The dead entities that annotation `candidate` references, transitively,
+/// each mapped to the entity it was first reached from, if the candidate
+/// owns them: none of them references a live entity (so the annotation does
+/// not revive dead data attached to live data, such as a dead face), and
+/// each is only referenced by the candidate, by other members or by other
+/// annotations (such as a deduplicated style they share). `None` if it does
+/// not own them.
+fn owned_data(
+    candidate: u32,
+    id_to_refs: &HashMap<u32, Vec<u32>>,
+    referrers: &HashMap<u32, Vec<u32>>,
+    is_candidate: &HashSet<u32>,
+    reachable: &HashMap<u32, Option<u32>>,
+) -> Option<HashMap<u32, Option<u32>>> {
+    let mut owned: HashMap<u32, Option<u32>> = HashMap::new();
+    let mut stack = vec![candidate];
+    while let Some(eid) = stack.pop() {
+        for &r in &id_to_refs[&eid] {
+            if reachable.contains_key(&r) {
+                if eid != candidate {
+                    return None;
+                }
+            } else if r != candidate && id_to_refs.contains_key(&r) && !owned.contains_key(&r) {
+                owned.insert(r, Some(eid));
+                stack.push(r);
+            }
+        }
+    }
+
+    let exclusive = owned.keys().all(|eid| {
+        referrers.get(eid).is_none_or(|refs| {
+            refs.iter()
+                .all(|r| *r == candidate || owned.contains_key(r) || is_candidate.contains(r))
+        })
+    });
+    exclusive.then_some(owned)
+}
+
+/// Map every entity of `lines` reachable from a GC root (see
+/// [`reachable_from_roots`]) to its new ID after orphan removal, reporting
+/// progress to and checking for cancellation with `monitor`. If no GC roots
+/// are found, all entities are kept.
+pub(crate) fn orphan_renumbering(
+    lines: &[impl Instance],
+    roots: &[String],
+    annotations: &[String],
+    monitor: &Monitor,
+) -> Result<HashMap<u32, u32>, Cancelled> {
+    let reachable = walk_from_roots(lines, roots, annotations, monitor)?;
+    Ok(renumber_reachable(lines, &reachable))
+}
+
+/// Number the entities in `reachable` from 1, in the order of `lines`. If
+/// `reachable` is empty (there is no GC root), every entity keeps its ID.
+fn renumber_reachable(
+    lines: &[impl Instance],
+    reachable: &HashMap<u32, Option<u32>>,
+) -> HashMap<u32, u32> {
+    let ids = lines.iter().filter_map(|line| Some(line.instance()?.0));
+    if reachable.is_empty() {
+        return ids.map(|eid| (eid, eid)).collect();
+    }
+    ids.filter(|eid| reachable.contains_key(eid))
+        .zip(1..)
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::lines;
+
+    fn renumbering(lines: &[String], roots: &[&str], annotations: &[&str]) -> HashMap<u32, u32> {
+        let roots: Vec<String> = roots.iter().map(|t| t.to_string()).collect();
+        let annotations: Vec<String> = annotations.iter().map(|t| t.to_string()).collect();
+        orphan_renumbering(lines, &roots, &annotations, &Monitor::default()).unwrap()
+    }
+
+    /// The original IDs of the kept entities, in ascending order.
+    fn kept(renumber: &HashMap<u32, u32>) -> Vec<u32> {
+        let mut ids: Vec<u32> = renumber.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
 
     #[test]
     fn removes_unreachable() {
-        let lines = vec![
-            "#1=APPLICATION_CONTEXT('core')".to_string(),
-            "#2=PRODUCT_DEFINITION('pd',#1)".to_string(),
-            "#3=CARTESIAN_POINT('',0.,0.,0.)".to_string(), // orphan
-        ];
-        let result = remove_orphans(&lines);
-        assert_eq!(result.len(), 2);
-        // The orphan CARTESIAN_POINT should be gone.
-        assert!(!result.iter().any(|l| l.contains("CARTESIAN_POINT")));
+        let lines = lines(&[
+            "#1=APPLICATION_CONTEXT('core')",
+            "#2=PRODUCT_DEFINITION('pd',#1)",
+            "#3=CARTESIAN_POINT('',0.,0.,0.)", // orphan
+        ]);
+        let renumber = renumbering(&lines, &[], &[]);
+        assert_eq!(renumber, HashMap::from([(1, 1), (2, 2)]));
+    }
+
+    #[test]
+    fn renumbers_in_file_order() {
+        let lines = lines(&[
+            "#5=CARTESIAN_POINT('',0.,0.,0.)", // orphan
+            "#7=PRODUCT_DEFINITION('pd',#9)",
+            "#9=CARTESIAN_POINT('',0.,0.,0.)",
+            "#3=APPLICATION_CONTEXT('core')",
+        ]);
+        let renumber = renumbering(&lines, &[], &[]);
+        assert_eq!(renumber, HashMap::from([(7, 1), (9, 2), (3, 3)]));
     }
 
     #[test]
     fn keeps_transitively_reachable() {
-        let lines = vec![
-            "#1=APPLICATION_CONTEXT('core')".to_string(),
-            "#2=PRODUCT_DEFINITION('pd',#3)".to_string(),
-            "#3=CARTESIAN_POINT('',0.,0.,0.)".to_string(), // reachable via #2
-        ];
-        let result = remove_orphans(&lines);
-        assert_eq!(result.len(), 3);
+        let lines = lines(&[
+            "#1=APPLICATION_CONTEXT('core')",
+            "#2=PRODUCT_DEFINITION('pd',#3)",
+            "#3=CARTESIAN_POINT('',0.,0.,0.)", // reachable via #2
+        ]);
+        assert_eq!(kept(&renumbering(&lines, &[], &[])), [1, 2, 3]);
+    }
+
+    #[test]
+    fn extra_roots() {
+        let lines = lines(&[
+            "#1=APPLICATION_CONTEXT('core')",
+            "#2=DRAUGHTING_CALLOUT('',(#3))",
+            "#3=CARTESIAN_POINT('',0.,0.,0.)",
+        ]);
+        assert_eq!(kept(&renumbering(&lines, &[], &[])), [1]);
+        assert_eq!(
+            kept(&renumbering(&lines, &["DRAUGHTING_CALLOUT"], &[])),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn no_roots_keeps_all() {
+        let lines = lines(&[
+            "#1=CARTESIAN_POINT('',0.,0.,0.)",
+            "#2=DIRECTION('',1.,0.,0.)",
+        ]);
+        let renumber = renumbering(&lines, &[], &[]);
+        assert_eq!(renumber, HashMap::from([(1, 1), (2, 2)]));
     }
 
     #[test]
-    fn no_roots_returns_all() {
-        let lines = vec![
-            "#1=CARTESIAN_POINT('',0.,0.,0.)".to_string(),
-            "#2=DIRECTION('',1.,0.,0.)".to_string(),
-        ];
-        let result = remove_orphans(&lines);
-        assert_eq!(result.len(), 2);
+    fn complex_instance_root() {
+        let lines = lines(&[
+            "#1=(REPRESENTATION_RELATIONSHIP('','',#2,#2) \
+             REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#3) \
+             SHAPE_REPRESENTATION_RELATIONSHIP())",
+            "#2=SHAPE_REPRESENTATION('',(),#4)",
+            "#3=ITEM_DEFINED_TRANSFORMATION('','',#4,#4)",
+            "#4=REPRESENTATION_CONTEXT('','')",
+            "#5=CARTESIAN_POINT('',0.,0.,0.)", // orphan
+        ]);
+        assert_eq!(kept(&renumbering(&lines, &[], &[])), [1, 2, 3, 4]);
     }
 
     #[test]
@@ -147,47 +362,99 @@ mod tests {
         // between a SHAPE_REPRESENTATION and an
         // ADVANCED_BREP_SHAPE_REPRESENTATION. Nothing references it by ID;
         // it must be a GC root so the BREP subtree is preserved.
-        let lines = vec![
-            "#1=SHAPE_DEFINITION_REPRESENTATION(#2,#3)".to_string(),
-            "#2=PRODUCT_DEFINITION_SHAPE('','',#10)".to_string(),
-            "#3=SHAPE_REPRESENTATION('',(#11),#12)".to_string(),
-            "#4=SHAPE_REPRESENTATION_RELATIONSHIP('','',#3,#5)".to_string(),
-            "#5=ADVANCED_BREP_SHAPE_REPRESENTATION('',(#6),#12)".to_string(),
-            "#6=MANIFOLD_SOLID_BREP('',#7)".to_string(),
-            "#7=CLOSED_SHELL('',(#8))".to_string(),
-            "#8=ADVANCED_FACE('',(#9),#13,.T.)".to_string(),
-            "#9=FACE_BOUND('',#14,.T.)".to_string(),
-            "#10=PRODUCT_DEFINITION('pd','',#15,#16)".to_string(),
-            "#11=AXIS2_PLACEMENT_3D('',#17,#18,#19)".to_string(),
-            "#12=REPRESENTATION_CONTEXT('','')".to_string(),
-            "#13=PLANE('',#11)".to_string(),
-            "#14=EDGE_LOOP('',(#20))".to_string(),
-            "#15=PRODUCT_DEFINITION_FORMATION('','',#21)".to_string(),
-            "#16=APPLICATION_CONTEXT('core')".to_string(),
-            "#17=CARTESIAN_POINT('',0.,0.,0.)".to_string(),
-            "#18=DIRECTION('',0.,0.,1.)".to_string(),
-            "#19=DIRECTION('',1.,0.,0.)".to_string(),
-            "#20=ORIENTED_EDGE('',*,*,#22,.T.)".to_string(),
-            "#21=PRODUCT('p','','',(#23))".to_string(),
-            "#22=EDGE_CURVE('',#24,#24,#25,.T.)".to_string(),
-            "#23=PRODUCT_CONTEXT('',#16,'')".to_string(),
-            "#24=VERTEX_POINT('',#17)".to_string(),
-            "#25=LINE('',#17,#26)".to_string(),
-            "#26=VECTOR('',#18,1.)".to_string(),
-        ];
-        let result = remove_orphans(&lines);
-        // The ADVANCED_BREP_SHAPE_REPRESENTATION subtree must survive
-        // because SHAPE_REPRESENTATION_RELATIONSHIP is a GC root.
-        assert!(
-            result
-                .iter()
-                .any(|l| l.contains("ADVANCED_BREP_SHAPE_REPRESENTATION")),
-            "ADVANCED_BREP_SHAPE_REPRESENTATION must be kept via SHAPE_REPRESENTATION_RELATIONSHIP root"
-        );
-        assert!(
-            result.iter().any(|l| l.contains("MANIFOLD_SOLID_BREP")),
-            "MANIFOLD_SOLID_BREP must be reachable from SHAPE_REPRESENTATION_RELATIONSHIP"
-        );
-        assert_eq!(result.len(), lines.len());
+        let lines = lines(&[
+            "#1=SHAPE_DEFINITION_REPRESENTATION(#2,#3)",
+            "#2=PRODUCT_DEFINITION_SHAPE('','',#10)",
+            "#3=SHAPE_REPRESENTATION('',(#11),#12)",
+            "#4=SHAPE_REPRESENTATION_RELATIONSHIP('','',#3,#5)",
+            "#5=ADVANCED_BREP_SHAPE_REPRESENTATION('',(#6),#12)",
+            "#6=MANIFOLD_SOLID_BREP('',#7)",
+            "#7=CLOSED_SHELL('',(#8))",
+            "#8=ADVANCED_FACE('',(#9),#13,.T.)",
+            "#9=FACE_BOUND('',#14,.T.)",
+            "#10=PRODUCT_DEFINITION('pd','',#15,#16)",
+            "#11=AXIS2_PLACEMENT_3D('',#17,#18,#19)",
+            "#12=REPRESENTATION_CONTEXT('','')",
+            "#13=PLANE('',#11)",
+            "#14=EDGE_LOOP('',(#20))",
+            "#15=PRODUCT_DEFINITION_FORMATION('','',#21)",
+            "#16=APPLICATION_CONTEXT('core')",
+            "#17=CARTESIAN_POINT('',0.,0.,0.)",
+            "#18=DIRECTION('',0.,0.,1.)",
+            "#19=DIRECTION('',1.,0.,0.)",
+            "#20=ORIENTED_EDGE('',*,*,#22,.T.)",
+            "#21=PRODUCT('p','','',(#23))",
+            "#22=EDGE_CURVE('',#24,#24,#25,.T.)",
+            "#23=PRODUCT_CONTEXT('',#16,'')",
+            "#24=VERTEX_POINT('',#17)",
+            "#25=LINE('',#17,#26)",
+            "#26=VECTOR('',#18,1.)",
+        ]);
+        // The ADVANCED_BREP_SHAPE_REPRESENTATION subtree (#5 to #9) must
+        // survive because SHAPE_REPRESENTATION_RELATIONSHIP is a GC root.
+        let renumber = renumbering(&lines, &[], &[]);
+        assert_eq!(kept(&renumber), (1..=26).collect::<Vec<_>>());
+    }
+
+    fn annotation_lines() -> Vec<String> {
+        lines(&[
+            "#1=APPLICATION_CONTEXT('core')",
+            "#2=PRODUCT_DEFINITION('pd',#1,#3)",
+            "#3=ADVANCED_FACE('',(),#4,.T.)",
+            "#4=PLANE('',#5)",
+            "#5=AXIS2_PLACEMENT_3D('',#6,$,$)",
+            "#6=CARTESIAN_POINT('',0.,0.,0.)",
+            // Colour of the live face, via exclusively owned style data.
+            "#7=STYLED_ITEM('color',(#8),#3)",
+            "#8=PRESENTATION_STYLE_ASSIGNMENT((#9))",
+            "#9=COLOUR_RGB('',1.,0.,0.)",
+            // Colour of an orphan face that is also referenced elsewhere.
+            "#10=STYLED_ITEM('color',(#8),#11)",
+            "#11=ADVANCED_FACE('',(),#4,.T.)",
+            "#12=CLOSED_SHELL('',(#11))",
+        ])
+    }
+
+    #[test]
+    fn drops_annotations_by_default() {
+        let renumber = renumbering(&annotation_lines(), &[], &[]);
+        assert_eq!(kept(&renumber), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn keeps_annotations_of_live_entities() {
+        let renumber = renumbering(&annotation_lines(), &[], &["STYLED_ITEM"]);
+        // The second styled item would resurrect the dead face, so it stays
+        // removed even though it shares its style with #7.
+        assert_eq!(kept(&renumber), (1..=9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_annotations_of_kept_annotations() {
+        let mut lines = annotation_lines();
+        lines.push("#13=PROPERTY_DEFINITION('note','',#7)".to_string());
+        let renumber = renumbering(&lines, &[], &["STYLED_ITEM", "PROPERTY_DEFINITION"]);
+        assert_eq!(kept(&renumber), [1, 2, 3, 4, 5, 6, 7, 8, 9, 13]);
+        assert_eq!(renumber[&13], 10);
+    }
+
+    #[test]
+    fn does_not_revive_dead_data() {
+        let lines = lines(&[
+            "#1=APPLICATION_CONTEXT('core')",
+            "#2=PRODUCT_DEFINITION('pd',#1,#3)",
+            "#3=PLANE('',#5)",
+            // An orphan face on the live plane, coloured by #6.
+            "#4=ADVANCED_FACE('',(#8),#3,.T.)",
+            "#5=CARTESIAN_POINT('',0.,0.,0.)",
+            "#6=STYLED_ITEM('color',(#7),#4)",
+            "#7=PRESENTATION_STYLE_ASSIGNMENT(())",
+            "#8=CARTESIAN_POINT('',1.,0.,0.)",
+            // Annotates the live plane, but its data points at the dead face.
+            "#9=PROPERTY_DEFINITION('note','',#3,#10)",
+            "#10=DESCRIPTIVE_REPRESENTATION_ITEM('',#4)",
+        ]);
+        let renumber = renumbering(&lines, &[], &["STYLED_ITEM", "PROPERTY_DEFINITION"]);
+        assert_eq!(renumber, HashMap::from([(1, 1), (2, 2), (3, 3), (5, 4)]));
     }
 }
@@ -1,57 +1,171 @@
-use std::io::BufRead;
+use std::borrow::Cow;
+
+use crate::{
+    find_numbers::find_numbers,
+    limits::{LimitError, Limits},
+    normalize::exponent,
+};
+
+/// Number literals quoted in a [`LimitError::Exponent`] are cut to this many
+/// bytes.
+const MAX_QUOTED_LITERAL: usize = 40;
 
 /// The three sections of a STEP file: everything before `DATA;`, the data
 /// entity lines, and everything from `ENDSEC;` onward.
+///
+/// The data entries are borrowed from the input where possible: entities on
+/// a single line of valid UTF-8 are not copied.
+pub(crate) struct Sections<'a> {
+    pub header: Vec<String>,
+    pub data: Vec<Cow<'a, str>>,
+    /// The 1-based line number where each entry of `data` starts.
+    pub lines: Vec<usize>,
+    pub footer: Vec<String>,
+}
+
+/// The header and data lines of a STEP file, see [`Sections`].
 pub(crate) struct ParseResult {
     pub header: Vec<String>,
     pub data: Vec<String>,
-    pub footer: Vec<String>,
 }
 
-/// Parse a STEP file from a buffered reader into its header, data, and footer
-/// sections.
+impl From<Sections<'_>> for ParseResult {
+    fn from(sections: Sections<'_>) -> Self {
+        Self {
+            header: sections.header,
+            data: sections.data.into_iter().map(Cow::into_owned).collect(),
+        }
+    }
+}
+
+/// An entity instance as read by the text-based passes: a data line, or the
+/// ID and body of an [`Entity`](crate::Entity), borrowed from it.
+pub(crate) trait Instance {
+    /// The instance ID and right-hand side, or `None` if this is not an
+    /// entity instance (e.g. a comment).
+    fn instance(&self) -> Option<(u32, &str)>;
+}
+
+/// A data line `#<id>=…`, whose right-hand side is everything after the `=`.
+impl Instance for String {
+    fn instance(&self) -> Option<(u32, &str)> {
+        let eq = self.find('=')?;
+        Some((self.get(1..eq)?.trim().parse().ok()?, &self[eq + 1..]))
+    }
+}
+
+impl Instance for (u32, Cow<'_, str>) {
+    fn instance(&self) -> Option<(u32, &str)> {
+        Some((self.0, &self.1))
+    }
+}
+
+/// Parse a STEP file into its header, data, and footer sections.
 ///
 /// Multi-line data entities (lines not ending with `;`) are joined into a
-/// single string. The header and footer lines are preserved verbatim (with
-/// trailing whitespace trimmed from header lines).
-pub(crate) fn parse_data_section(reader: impl BufRead) -> ParseResult {
-    let mut result = ParseResult {
+/// single string. Blank lines inside DATA are skipped, also in the middle
+/// of an entity. Invalid UTF-8 is replaced with U+FFFD. The header and
+/// footer lines are preserved verbatim (with trailing whitespace trimmed
+/// from header lines).
+pub(crate) fn parse_data_section(input: &[u8]) -> ParseResult {
+    parse_data_section_limited(input, &Limits::default())
+        .expect("parsing without limits cannot fail")
+}
+
+/// Like [`parse_data_section`], but fail as soon as the input exceeds one of
+/// `limits` (except `max_file_size`, which the caller checks).
+pub(crate) fn parse_data_section_limited(
+    input: &[u8],
+    limits: &Limits,
+) -> Result<ParseResult, LimitError> {
+    split_sections(input, limits).map(ParseResult::from)
+}
+
+/// Like [`parse_data_section_limited`], but keep the footer and line
+/// numbers and borrow the data entries from `input` where possible.
+pub(crate) fn split_sections<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+) -> Result<Sections<'a>, LimitError> {
+    let mut result = Sections {
         header: Vec::new(),
         data: Vec::new(),
+        lines: Vec::new(),
         footer: Vec::new(),
     };
 
     let mut past_header = false;
     let mut past_data = false;
     let mut continuing = false;
+    // 1-based line number where the current entity starts.
+    let mut entity_line = 0;
 
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => break,
-        };
+    for (index, line) in lines(input).enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        // STEP files are ASCII; don't let a stray non-UTF-8 byte end parsing.
+        let line = String::from_utf8_lossy(line);
+        if let Some(limit) = limits.max_line_length
+            && line.len() > limit
+        {
+            return Err(LimitError::LineLength {
+                line: index + 1,
+                limit,
+            });
+        }
 
         if past_header {
             if past_data || line.contains("ENDSEC;") {
                 past_data = true;
-                result.footer.push(line);
+                result.footer.push(line.into_owned());
             } else {
-                let trimmed = line.trim().to_string();
+                let ends_entity = line.trim_end().ends_with(';');
+                let trimmed = match line {
+                    Cow::Borrowed(line) => Cow::Borrowed(line.trim()),
+                    Cow::Owned(line) => Cow::Owned(line.trim().to_string()),
+                };
+                if trimmed.is_empty() {
+                    // Blank lines neither start nor end an entity.
+                    continue;
+                }
 
                 if continuing {
+                    let entity = result.data.last_mut().unwrap().to_mut();
                     if trimmed
                         .as_bytes()
                         .first()
                         .is_some_and(|b| b.is_ascii_alphabetic())
                     {
-                        result.data.last_mut().unwrap().push(' ');
+                        entity.push(' ');
                     }
-                    result.data.last_mut().unwrap().push_str(&trimmed);
+                    entity.push_str(&trimmed);
                 } else {
+                    entity_line = index + 1;
+                    if let Some(limit) = limits.max_entities
+                        && result.data.len() >= limit
+                    {
+                        return Err(LimitError::EntityCount {
+                            line: entity_line,
+                            limit,
+                        });
+                    }
                     result.data.push(trimmed);
+                    result.lines.push(entity_line);
+                }
+
+                let entity = result.data.last().unwrap();
+                if let Some(limit) = limits.max_line_length
+                    && entity.len() > limit
+                {
+                    return Err(LimitError::LineLength {
+                        line: entity_line,
+                        limit,
+                    });
                 }
 
-                continuing = !line.trim_end().ends_with(';');
+                continuing = !ends_entity;
+                if !continuing && let Some(limit) = limits.max_exponent {
+                    check_exponents(entity, entity_line, limit)?;
+                }
             }
         } else {
             if line.contains("DATA;") {
@@ -61,13 +175,42 @@ pub(crate) fn parse_data_section(reader: impl BufRead) -> ParseResult {
         }
     }
 
-    result
+    if continuing && let (Some(limit), Some(entity)) = (limits.max_exponent, result.data.last()) {
+        check_exponents(entity, entity_line, limit)?;
+    }
+
+    Ok(result)
+}
+
+/// The lines of `input`, without the `\n`s. A final `\n` does not start
+/// another line.
+fn lines(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let text = input.strip_suffix(b"\n").unwrap_or(input);
+    text.split(|&b| b == b'\n')
+        .take(if input.is_empty() { 0 } else { usize::MAX })
+}
+
+/// Fail if a number literal in `entity` has an exponent beyond `limit`.
+fn check_exponents(entity: &str, line: usize, limit: u32) -> Result<(), LimitError> {
+    for m in find_numbers(entity) {
+        let literal = &entity[m.start..m.end];
+        if exponent(literal).unsigned_abs() > u64::from(limit) {
+            let mut end = literal.len().min(MAX_QUOTED_LITERAL);
+            while !literal.is_char_boundary(end) {
+                end -= 1;
+            }
+            return Err(LimitError::Exponent {
+                line,
+                literal: literal[..end].to_string(),
+                limit,
+            });
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
-
     use super::*;
 
     #[test]
@@ -82,8 +225,7 @@ DATA;
 ENDSEC;
 END-ISO-10303-21;
 ";
-        let reader = Cursor::new(input);
-        let result = parse_data_section(reader);
+        let result = split_sections(input.as_bytes(), &Limits::default()).unwrap();
 
         assert_eq!(result.header.len(), 4); // HEADER; through DATA;
         assert_eq!(result.data.len(), 2);
@@ -101,11 +243,130 @@ DATA;
 #5=SHORT('bar');
 ENDSEC;
 ";
-        let reader = Cursor::new(input);
-        let result = parse_data_section(reader);
+        let result = parse_data_section(input.as_bytes());
 
         assert_eq!(result.data.len(), 2);
         assert!(result.data[0].contains("#2,#3,"));
         assert!(result.data[0].ends_with(';'));
     }
+
+    #[test]
+    fn invalid_utf8() {
+        let input = b"DATA;\n#1=PRODUCT('\xb8');\n#2=PRODUCT('x');\nENDSEC;\n";
+        let result = split_sections(input, &Limits::default()).unwrap();
+
+        assert_eq!(result.data, ["#1=PRODUCT('\u{fffd}');", "#2=PRODUCT('x');"]);
+        assert_eq!(result.footer, ["ENDSEC;"]);
+    }
+
+    #[test]
+    fn borrowed_entries() {
+        let input = b"DATA;\n  #1=A('\xb8');\n#2=B(\n#1);\r\n#3=C(#2); \r\nENDSEC;\n";
+        let result = split_sections(input, &Limits::default()).unwrap();
+
+        assert_eq!(result.data, ["#1=A('\u{fffd}');", "#2=B(#1);", "#3=C(#2);"]);
+        assert_eq!(result.lines, [2, 3, 5]);
+        // Only the lossily decoded and the joined entries are copies.
+        assert!(matches!(
+            &result.data[..],
+            [Cow::Owned(_), Cow::Owned(_), Cow::Borrowed(_)]
+        ));
+    }
+
+    #[test]
+    fn final_newline() {
+        for (input, footer) in [
+            ("DATA;\nENDSEC;", &["ENDSEC;"][..]),
+            ("DATA;\nENDSEC;\n", &["ENDSEC;"]),
+            ("DATA;\nENDSEC;\n\n", &["ENDSEC;", ""]),
+        ] {
+            let result = split_sections(input.as_bytes(), &Limits::default()).unwrap();
+            assert_eq!(result.footer, footer, "{input:?}");
+        }
+        assert!(
+            split_sections(b"", &Limits::default())
+                .unwrap()
+                .header
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn blank_lines() {
+        let input = "\
+DATA;
+
+#1=LONG_ENTITY('foo',
+
+ITEM);
+
+ENDSEC;
+";
+        let result = parse_data_section(input.as_bytes());
+
+        assert_eq!(result.data, ["#1=LONG_ENTITY('foo', ITEM);"]);
+    }
+
+    mod limits {
+        use super::*;
+
+        const INPUT: &str = "\
+DATA;
+#1=LONG_ENTITY('foo',
+#2,#3,
+#4);
+#5=CARTESIAN_POINT('',(1.E999999999,0.,0.));
+ENDSEC;
+";
+
+        fn parse(limits: Limits) -> Result<ParseResult, LimitError> {
+            parse_data_section_limited(INPUT.as_bytes(), &limits)
+        }
+
+        #[test]
+        fn unlimited() {
+            assert_eq!(parse(Limits::default()).unwrap().data.len(), 2);
+        }
+
+        #[test]
+        fn line_length() {
+            let limits = Limits {
+                max_line_length: Some(25),
+                ..Default::default()
+            };
+            // The physical lines fit, but not the joined entity.
+            assert_eq!(
+                parse(limits).err(),
+                Some(LimitError::LineLength { line: 2, limit: 25 })
+            );
+        }
+
+        #[test]
+        fn entity_count() {
+            let limits = Limits {
+                max_entities: Some(1),
+                ..Default::default()
+            };
+            assert_eq!(
+                parse(limits).err(),
+                Some(LimitError::EntityCount { line: 5, limit: 1 })
+            );
+        }
+
+        #[test]
+        fn exponent() {
+            let limits = Limits {
+                max_exponent: Some(308),
+                ..Default::default()
+            };
+            assert_eq!(
+                parse(limits).err(),
+                Some(LimitError::Exponent {
+                    line: 5,
+                    literal: "1.E999999999".to_string(),
+                    limit: 308
+                })
+            );
+        }
+    }
 }
@@ -0,0 +1,778 @@
+//! The reduction pipeline: a sequence of passes over a [`StepFile`].
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    Entity, Error, ReduceOptions, ReduceStats, StepFile, ValidationReport, check_options, context,
+    deduplicate::{self, DedupTrace, Merge},
+    normalize::{self, PrecisionPolicy},
+    orphans, precision_policy,
+    progress::Monitor,
+    validate, verify,
+};
+
+/// One step of a [`Pipeline`], transforming the entities of a file in place.
+///
+/// A pass may modify, add and remove entities. Removed entities count as
+/// removed in [`ReduceStats`]; to renumber entities or merge them into
+/// others, use [`PassContext::renumber`] so that the pipeline keeps track of
+/// where each original entity went.
+///
+/// ```
+/// use stepreduce::{Error, Pass, PassContext, StepFile};
+///
+/// /// Drop all `PROPERTY_DEFINITION`s.
+/// struct StripProperties;
+///
+/// impl Pass for StripProperties {
+///     fn name(&self) -> &str {
+///         "strip properties"
+///     }
+///
+///     fn run(&mut self, file: &mut StepFile, _: &mut PassContext) -> Result<bool, Error> {
+///         let before = file.entities.len();
+///         file.entities.retain(|e| e.type_name() != "PROPERTY_DEFINITION");
+///         Ok(file.entities.len() != before)
+///     }
+/// }
+/// ```
+pub trait Pass {
+    /// A short name for diagnostics.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Run the pass over `file` and return whether anything changed that
+    /// the other passes could act on. Dropping entities that nothing refers
+    /// to need not count, see [`RemoveOrphans`].
+    fn run(&mut self, file: &mut StepFile, context: &mut PassContext) -> Result<bool, Error>;
+}
+
+/// State shared by the passes of one [`Pipeline`] run.
+pub struct PassContext<'a> {
+    options: &'a ReduceOptions,
+    pub(crate) policy: PrecisionPolicy,
+    /// The entities of the input. Entities parsed from a shared input only
+    /// refer to their text in it.
+    original: Vec<Entity>,
+    /// The current ID of every original entity that was not removed.
+    ids: HashMap<u32, u32>,
+    /// The original ID of every current entity that has one (the smallest
+    /// one for merged entities).
+    origins: HashMap<u32, u32>,
+    merged: usize,
+    labels_lost: usize,
+    quantize_min_decimals: Option<u32>,
+    quantized: bool,
+    /// The merges so far, if the run is traced.
+    trace: Option<DedupTrace>,
+}
+
+impl<'a> PassContext<'a> {
+    fn new(options: &'a ReduceOptions, file: &StepFile) -> Self {
+        let ids: HashMap<u32, u32> = file.entities.iter().map(|e| (e.id(), e.id())).collect();
+        Self {
+            options,
+            policy: precision_policy(&instances(&file.entities), options),
+            original: file.entities.clone(),
+            origins: ids.clone(),
+            ids,
+            merged: 0,
+            labels_lost: 0,
+            quantize_min_decimals: None,
+            quantized: false,
+            trace: None,
+        }
+    }
+
+    /// The options the pipeline was built with.
+    pub fn options(&self) -> &ReduceOptions {
+        self.options
+    }
+
+    /// The ID that the entity with (current) ID `id` had in the input, or
+    /// `None` if it was added by a pass. Merged entities report the smallest
+    /// original ID.
+    pub fn original_id(&self, id: u32) -> Option<u32> {
+        self.origins.get(&id).copied()
+    }
+
+    /// Renumber the entities of `file` by `map` (current ID → new ID) and
+    /// remap all references accordingly. Entities missing from `map` are
+    /// removed; of several entities mapped to the same new ID, the first
+    /// one is kept and the others are merged into it.
+    pub fn renumber(&mut self, file: &mut StepFile, map: &HashMap<u32, u32>) {
+        let mut kept = HashSet::with_capacity(map.len());
+        file.entities
+            .retain_mut(|entity| match map.get(&entity.id()) {
+                Some(&new) if kept.insert(new) => {
+                    entity.set_id(new);
+                    entity.remap_references(map);
+                    true
+                }
+                _ => false,
+            });
+
+        self.ids.retain(|_, id| match map.get(id) {
+            Some(&new) => {
+                *id = new;
+                true
+            }
+            None => false,
+        });
+        self.rebuild_origins();
+    }
+
+    /// Forget original entities whose current entity is gone.
+    fn sync(&mut self, file: &StepFile) {
+        if self.origins.len() == file.entities.len()
+            && file
+                .entities
+                .iter()
+                .all(|e| self.origins.contains_key(&e.id()))
+        {
+            return;
+        }
+        let present: HashSet<u32> = file.entities.iter().map(|e| e.id()).collect();
+        self.ids.retain(|_, id| present.contains(id));
+        self.rebuild_origins();
+    }
+
+    fn rebuild_origins(&mut self) {
+        self.origins.clear();
+        for (&original, &id) in &self.ids {
+            self.origins
+                .entry(id)
+                .and_modify(|o| *o = (*o).min(original))
+                .or_insert(original);
+        }
+    }
+
+    /// The original ID that selects the precision of entity `id`.
+    fn precision_origin(&self, id: u32) -> u32 {
+        // Added entities use the default precision.
+        self.original_id(id).unwrap_or(u32::MAX)
+    }
+}
+
+/// The ID and body of each of `entities`, for the text-based passes. Bodies
+/// are borrowed unless the entity was modified.
+pub(crate) fn instances(entities: &[Entity]) -> Vec<(u32, Cow<'_, str>)> {
+    entities.iter().map(|e| (e.id(), e.body())).collect()
+}
+
+/// What a traced [`Pipeline`] run did to each original entity.
+#[derive(Debug, Default)]
+pub(crate) struct Trace {
+    /// Merges keyed by the original ID of the entity that was merged away.
+    pub merges: HashMap<u32, Merge>,
+    /// The output ID of every original entity that was not removed.
+    pub ids: HashMap<u32, u32>,
+    /// The original ID of every output entity that has one (the smallest one
+    /// for merged entities).
+    pub origins: HashMap<u32, u32>,
+}
+
+/// Rewrite the body of every entity with `rewrite(body, original ID)`,
+/// returning whether any entity changed. Entities with a
+/// [`Parameter::Raw`](crate::Parameter::Raw) are left alone, as it is not
+/// clear what the numbers in them are.
+fn rewrite_bodies(
+    file: &mut StepFile,
+    context: &PassContext,
+    rewrite: impl Fn(&str, u32) -> String,
+) -> Result<bool, Error> {
+    let mut changed = false;
+    for entity in &mut file.entities {
+        if entity.has_raw_parameters() {
+            continue;
+        }
+        let body = entity.body();
+        let rewritten = rewrite(&body, context.precision_origin(entity.id()));
+        if rewritten != body {
+            *entity = format!("#{}={rewritten};", entity.id()).parse()?;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+/// Merge entities that are equal up to the precision and name handling of
+/// the [`ReduceOptions`], repeating until no more merges are possible.
+/// Entities that carry identity (such as `PRODUCT`) are never merged.
+/// Renumbers the entities from 1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deduplicate;
+
+impl Pass for Deduplicate {
+    fn name(&self) -> &str {
+        "deduplicate"
+    }
+
+    fn run(&mut self, file: &mut StepFile, context: &mut PassContext) -> Result<bool, Error> {
+        let monitor = Monitor::new(context.options);
+        let mut changed = false;
+        let mut trace = context.trace.take();
+        for pass in 1.. {
+            let step = deduplicate::deduplicate_pass(
+                &instances(&file.entities),
+                &context.policy,
+                context.options.names,
+                &context.options.identity_entities,
+                &|id| context.precision_origin(id),
+                pass,
+                &monitor,
+                trace.as_mut(),
+            )?;
+            let merged = file.entities.len() - step.kept.len();
+            context.merged += merged;
+            context.labels_lost += step.labels_lost;
+            context.renumber(file, &step.lookup);
+            if merged == 0 {
+                break;
+            }
+            changed = true;
+        }
+        context.trace = trace;
+        Ok(changed)
+    }
+}
+
+/// Remove entities that are not reachable from a GC root (such as
+/// `PRODUCT_DEFINITION`), keeping the
+/// [`annotation_entities`](ReduceOptions::annotation_entities) that annotate
+/// reachable ones. Renumbers the entities from 1, unless the file has no GC
+/// root, in which case nothing is removed.
+///
+/// Never reports a change: no remaining entity refers to a removed one, so
+/// the remaining entities are the same as before up to their IDs and
+/// [`Deduplicate`] finds nothing new to merge.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOrphans;
+
+impl Pass for RemoveOrphans {
+    fn name(&self) -> &str {
+        "remove orphans"
+    }
+
+    fn run(&mut self, file: &mut StepFile, context: &mut PassContext) -> Result<bool, Error> {
+        let renumber = orphans::orphan_renumbering(
+            &instances(&file.entities),
+            &context.options.root_entities,
+            &context.options.annotation_entities,
+            &Monitor::new(context.options),
+        )?;
+        context.renumber(file, &renumber);
+        Ok(false)
+    }
+}
+
+/// Write every REAL rounded to the effective precision, see
+/// [`ReduceOptions::quantize`]. Does nothing if no precision is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quantize;
+
+impl Pass for Quantize {
+    fn name(&self) -> &str {
+        "quantize"
+    }
+
+    fn run(&mut self, file: &mut StepFile, context: &mut PassContext) -> Result<bool, Error> {
+        if context.policy.is_exact() {
+            return Ok(false);
+        }
+        let guard = context::context_decimals(&instances(&context.original));
+        context.quantized = true;
+        context.quantize_min_decimals = guard.fallback;
+        rewrite_bodies(file, context, |body, origin| {
+            normalize::quantize_numbers_in_line(body, &context.policy, origin, guard.get(origin))
+        })
+    }
+}
+
+/// Rewrite every REAL to its shortest exact form, see
+/// [`ReduceOptions::shorten_reals`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShortenReals;
+
+impl Pass for ShortenReals {
+    fn name(&self) -> &str {
+        "shorten reals"
+    }
+
+    fn run(&mut self, file: &mut StepFile, context: &mut PassContext) -> Result<bool, Error> {
+        rewrite_bodies(file, context, |body, _| {
+            normalize::shorten_numbers_in_line(body)
+        })
+    }
+}
+
+/// A configurable reduction: parse, run passes, check, write.
+///
+/// [`Pipeline::standard`] is what [`reduce`](crate::reduce) runs. Custom
+/// pipelines mix the built-in passes ([`Deduplicate`], [`RemoveOrphans`],
+/// [`Quantize`], [`ShortenReals`]) with their own:
+///
+/// ```
+/// # use stepreduce::{Error, Pass, PassContext, StepFile};
+/// # struct StripProperties;
+/// # impl Pass for StripProperties {
+/// #     fn run(&mut self, _: &mut StepFile, _: &mut PassContext) -> Result<bool, Error> {
+/// #         Ok(false)
+/// #     }
+/// # }
+/// use stepreduce::{Deduplicate, Pipeline, ReduceOptions, RemoveOrphans};
+///
+/// let mut pipeline = Pipeline::new(ReduceOptions::default())
+///     .pass(StripProperties)
+///     .pass(Deduplicate)
+///     .pass(RemoveOrphans)
+///     .repeat_until_unchanged(true);
+/// # let input = b"DATA;\n#1=PRODUCT_DEFINITION('',$,#2,#2);\n#2=FOO();\n#3=FOO();\nENDSEC;\n";
+/// let (output, stats) = pipeline.run(input)?;
+/// # assert_eq!(stats.output_entities, 2);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Pipeline {
+    options: ReduceOptions,
+    passes: Vec<Box<dyn Pass>>,
+    repeat: bool,
+    finally: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    /// A pipeline without passes. `options` select the precision and name
+    /// handling of the built-in passes, the limits, and verification.
+    pub fn new(options: ReduceOptions) -> Self {
+        Self {
+            options,
+            passes: Vec::new(),
+            repeat: false,
+            finally: Vec::new(),
+        }
+    }
+
+    /// The pipeline of [`reduce`](crate::reduce): [`Deduplicate`] and
+    /// [`RemoveOrphans`] repeated until neither changes anything, followed
+    /// by [`Quantize`] and [`ShortenReals`] if enabled in `options`.
+    pub fn standard(options: ReduceOptions) -> Self {
+        let (quantize, shorten_reals) = (options.quantize, options.shorten_reals);
+        let mut pipeline = Self::new(options)
+            .pass(Deduplicate)
+            .pass(RemoveOrphans)
+            .repeat_until_unchanged(true);
+        if quantize {
+            pipeline = pipeline.finally(Quantize);
+        }
+        if shorten_reals {
+            pipeline = pipeline.finally(ShortenReals);
+        }
+        pipeline
+    }
+
+    /// Append `pass`.
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Append `pass` to the passes that run once, after the others.
+    pub fn finally(mut self, pass: impl Pass + 'static) -> Self {
+        self.finally.push(Box::new(pass));
+        self
+    }
+
+    /// Run the passes in rounds until every pass has run once since the
+    /// last change, i.e. until a fixed point is reached (assuming that
+    /// running a pass twice in a row changes nothing the second time).
+    /// [`ReduceOptions::max_rounds`] caps the number of rounds.
+    pub fn repeat_until_unchanged(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Reduce STEP file content, like [`reduce_with_stats`](crate::reduce_with_stats).
+    pub fn run(&mut self, input: &[u8]) -> Result<(Vec<u8>, ReduceStats), Error> {
+        // Fail before parsing if the options are invalid.
+        check_options(&self.options)?;
+        let monitor = Monitor::new(&self.options);
+        let file = StepFile::parse_monitored(input, &self.options.limits, &monitor)?;
+        self.run_and_write(file, Vec::with_capacity(input.len()))
+    }
+
+    /// Reduce the STEP file at `path`, like [`reduce_file`](crate::reduce_file).
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified until this returns, see
+    /// [`StepFile::parse_file`].
+    #[cfg(feature = "mmap")]
+    pub unsafe fn run_path(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(Vec<u8>, ReduceStats), Error> {
+        check_options(&self.options)?;
+        let monitor = Monitor::new(&self.options);
+        // SAFETY: passed on to the caller.
+        let file = unsafe {
+            StepFile::parse_file_monitored(path.as_ref(), &self.options.limits, &monitor)
+        }?;
+        self.run_and_write(file, Vec::new())
+    }
+
+    /// Run the passes over `file` and append it to `output`.
+    fn run_and_write(
+        &mut self,
+        mut file: StepFile,
+        mut output: Vec<u8>,
+    ) -> Result<(Vec<u8>, ReduceStats), Error> {
+        let stats = self.run_file(&mut file)?;
+        file.write_monitored(&mut output, &Monitor::new(&self.options))
+            .expect("writing to a Vec should not fail");
+        Ok((output, stats))
+    }
+
+    /// Run the passes over a parsed file in place. Fails with
+    /// [`Error::InvalidOptions`] if the options are invalid.
+    pub fn run_file(&mut self, file: &mut StepFile) -> Result<ReduceStats, Error> {
+        self.run_file_traced(file, None)
+    }
+
+    /// [`run_file`](Self::run_file), recording in `trace` what happened to
+    /// each original entity.
+    pub(crate) fn run_file_traced(
+        &mut self,
+        file: &mut StepFile,
+        trace: Option<&mut Trace>,
+    ) -> Result<ReduceStats, Error> {
+        let options = &self.options;
+        check_options(options)?;
+        let monitor = Monitor::new(options);
+        let mut context = PassContext::new(options, file);
+        if trace.is_some() {
+            context.trace = Some(DedupTrace::default());
+        }
+
+        let mut rounds = 0;
+        // The index of the pass that last changed something.
+        let mut last_change = None;
+        'rounds: loop {
+            for (i, pass) in self.passes.iter_mut().enumerate() {
+                if last_change == Some(i) {
+                    // Nothing changed since this pass last ran.
+                    break 'rounds;
+                }
+                if i == 0 {
+                    rounds += 1;
+                    if let Some(trace) = &mut context.trace {
+                        trace.round = rounds;
+                    }
+                }
+                monitor.check()?;
+                if pass.run(file, &mut context)? {
+                    last_change = Some(i);
+                }
+                context.sync(file);
+            }
+            if !self.repeat
+                || last_change.is_none()
+                || options.max_rounds.is_some_and(|max| rounds >= max)
+            {
+                break;
+            }
+        }
+        for pass in &mut self.finally {
+            monitor.check()?;
+            pass.run(file, &mut context)?;
+            context.sync(file);
+        }
+
+        let input_entities = context.original.len();
+        let output_entities = file.entities.len();
+        let stats = ReduceStats {
+            input_entities,
+            output_entities,
+            merged: context.merged,
+            removed: input_entities.saturating_sub(output_entities + context.merged),
+            labels_lost: context.labels_lost,
+            quantized: context.quantized,
+            quantize_min_decimals: context.quantize_min_decimals,
+            rounds,
+        };
+
+        if options.verify {
+            verify::verify_quotient(
+                &instances(&context.original),
+                &instances(&file.entities),
+                &context.ids,
+                &context.policy,
+                options.names,
+            )?;
+        }
+
+        // In debug builds, verification also makes sure the reduction did not
+        // break referential integrity. Issues already present in the input
+        // are tolerated.
+        if cfg!(debug_assertions) && options.verify {
+            let lines = |entities: &[Entity]| -> Vec<String> {
+                entities.iter().map(|e| e.to_string()).collect()
+            };
+            let issues = validate::new_issues(
+                &validate::validate_data(&lines(&context.original)),
+                validate::validate_data(&lines(&file.entities)),
+                &context.ids,
+            );
+            if !issues.is_empty() {
+                return Err(Error::Invalid(ValidationReport { issues }));
+            }
+        }
+
+        if let Some(trace) = trace {
+            trace.merges = context.trace.take().unwrap_or_default().merges;
+            trace.ids = context.ids;
+            trace.origins = context.origins;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &[u8] = b"ISO-10303-21;
+HEADER;
+ENDSEC;
+DATA;
+#1=PRODUCT_DEFINITION('',$,#2,#3);
+#2=CARTESIAN_POINT('',(0.,0.,1.));
+#3=CARTESIAN_POINT('',(0.,0.,1.));
+#4=PROPERTY_DEFINITION('',$,#1);
+#5=FOO(#2);
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    /// Drops all entities of one type.
+    struct Strip(&'static str);
+
+    impl Pass for Strip {
+        fn run(&mut self, file: &mut StepFile, _: &mut PassContext) -> Result<bool, Error> {
+            let before = file.entities.len();
+            file.entities.retain(|e| e.type_name() != self.0);
+            Ok(file.entities.len() != before)
+        }
+    }
+
+    fn data(output: &[u8]) -> Vec<&str> {
+        let output = std::str::from_utf8(output).unwrap();
+        output.lines().filter(|l| l.starts_with('#')).collect()
+    }
+
+    mod run {
+        use super::*;
+        use crate::Precision;
+
+        #[test]
+        fn standard_matches_reduce() {
+            let options = ReduceOptions {
+                shorten_reals: true,
+                verify: true,
+                ..Default::default()
+            };
+            let (output, stats) = Pipeline::standard(options.clone()).run(INPUT).unwrap();
+            // Removing orphans leaves nothing new to merge.
+            assert_eq!(stats.rounds, 1);
+            assert_eq!(
+                (output, stats),
+                crate::reduce_with_stats(INPUT, &options).unwrap()
+            );
+        }
+
+        #[test]
+        fn invalid_options() {
+            for precision in [Precision::SignificantDigits(0), Precision::Absolute(-1.0)] {
+                let options = ReduceOptions {
+                    type_precision: HashMap::from([("CARTESIAN_POINT".to_string(), precision)]),
+                    ..Default::default()
+                };
+                let result = Pipeline::standard(options).run(INPUT);
+                assert!(
+                    matches!(result, Err(Error::InvalidOptions(_))),
+                    "{precision}"
+                );
+            }
+        }
+
+        #[test]
+        fn custom_pass() {
+            let mut pipeline = Pipeline::new(ReduceOptions::default())
+                .pass(Strip("PROPERTY_DEFINITION"))
+                .pass(Deduplicate);
+            let (output, stats) = pipeline.run(INPUT).unwrap();
+            assert_eq!(
+                data(&output),
+                [
+                    "#1=PRODUCT_DEFINITION('',$,#2,#2);",
+                    "#2=CARTESIAN_POINT('',(0.,0.,1.));",
+                    "#3=FOO(#2);",
+                ]
+            );
+            assert_eq!(stats.input_entities, 5);
+            assert_eq!(stats.merged, 1);
+            assert_eq!(stats.removed, 1);
+        }
+
+        #[test]
+        fn repeat_until_unchanged() {
+            // FOO and BAR are only orphaned once the root that keeps them
+            // alive is gone, which happens after the orphan walk.
+            let input = b"DATA;
+#1=PRODUCT_DEFINITION('',$,$,$);
+#2=SHAPE_DEFINITION_REPRESENTATION(#3,#4);
+#3=FOO();
+#4=BAR();
+ENDSEC;
+";
+            let remaining = |repeat, max_rounds| {
+                let options = ReduceOptions {
+                    max_rounds,
+                    ..Default::default()
+                };
+                let mut pipeline = Pipeline::new(options)
+                    .pass(RemoveOrphans)
+                    .pass(Strip("SHAPE_DEFINITION_REPRESENTATION"))
+                    .repeat_until_unchanged(repeat);
+                let (output, stats) = pipeline.run(input).unwrap();
+                (data(&output).len(), stats.rounds)
+            };
+            assert_eq!(remaining(false, None), (3, 1));
+            assert_eq!(remaining(true, None), (1, 2));
+            assert_eq!(remaining(true, Some(1)), (3, 1));
+        }
+
+        #[test]
+        fn verify_renumbered() {
+            let options = ReduceOptions {
+                verify: true,
+                ..Default::default()
+            };
+            let mut pipeline = Pipeline::new(options).pass(RemoveOrphans).pass(Deduplicate);
+            assert!(pipeline.run(INPUT).is_ok());
+        }
+
+        #[test]
+        fn raw_parameters() {
+            // Parameters that are not valid Part 21 are kept as they are,
+            // and so are the numbers next to them.
+            let input = b"DATA;
+#1=PRODUCT_DEFINITION('',$,#2,#3);
+#2=FOO(99999999999999999999,&SCOPE,1.50);
+#3=FOO(..50,1.50,#2);
+ENDSEC;
+";
+            let options = ReduceOptions {
+                shorten_reals: true,
+                verify: true,
+                ..Default::default()
+            };
+            let (output, _) = Pipeline::standard(options).run(input).unwrap();
+            assert_eq!(
+                data(&output),
+                [
+                    "#1=PRODUCT_DEFINITION('',$,#2,#3);",
+                    "#2=FOO(99999999999999999999,&SCOPE,1.50);",
+                    "#3=FOO(..50,1.50,#2);",
+                ]
+            );
+        }
+    }
+
+    mod progress {
+        use std::sync::{Arc, Mutex};
+
+        use super::*;
+        use crate::{CancellationToken, Phase, ProgressCallback};
+
+        fn reported(cancel_in: Option<Phase>) -> (Vec<Phase>, Result<(), Error>) {
+            let phases = Arc::new(Mutex::new(Vec::new()));
+            let cancel = CancellationToken::new();
+            let progress = ProgressCallback::new({
+                let (phases, cancel) = (phases.clone(), cancel.clone());
+                move |phase, fraction| {
+                    assert!((0.0..=1.0).contains(&fraction));
+                    let mut phases = phases.lock().unwrap();
+                    if phases.last() != Some(&phase) {
+                        phases.push(phase);
+                    }
+                    if cancel_in == Some(phase) {
+                        cancel.cancel();
+                    }
+                }
+            });
+            let options = ReduceOptions {
+                progress: Some(progress),
+                cancel: Some(cancel),
+                ..Default::default()
+            };
+            let result = crate::reduce(INPUT, &options).map(|_| ());
+            let phases = phases.lock().unwrap().clone();
+            (phases, result)
+        }
+
+        #[test]
+        fn phases() {
+            let (phases, result) = reported(None);
+            assert!(result.is_ok());
+            assert_eq!(
+                phases,
+                [
+                    Phase::Parse,
+                    Phase::Deduplicate { pass: 1 },
+                    Phase::Deduplicate { pass: 2 },
+                    Phase::RemoveOrphans,
+                    Phase::Write,
+                ]
+            );
+        }
+
+        #[test]
+        fn cancel() {
+            for phase in [
+                Phase::Parse,
+                Phase::Deduplicate { pass: 1 },
+                Phase::RemoveOrphans,
+            ] {
+                let (phases, result) = reported(Some(phase));
+                assert!(matches!(result, Err(Error::Cancelled)), "{phase}");
+                assert_eq!(phases.last(), Some(&phase));
+            }
+        }
+    }
+
+    mod pass_context {
+        use super::*;
+
+        #[test]
+        fn original_id() {
+            struct Check;
+
+            impl Pass for Check {
+                fn run(&mut self, file: &mut StepFile, c: &mut PassContext) -> Result<bool, Error> {
+                    let ids: Vec<_> = file.entities.iter().map(|e| e.id()).collect();
+                    let origins: Vec<_> = ids.iter().map(|&id| c.original_id(id)).collect();
+                    assert_eq!(origins, [Some(1), Some(2), Some(4), Some(5)]);
+                    Ok(false)
+                }
+            }
+
+            let mut pipeline = Pipeline::new(ReduceOptions::default())
+                .pass(Deduplicate)
+                .pass(Check);
+            pipeline.run(INPUT).unwrap();
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Progress reporting and cancellation of long reductions.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{Error, ReduceOptions};
+
+/// A phase of a reduction, as reported to a [`ProgressCallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Parsing the input.
+    Parse,
+    /// One pass of deduplication, counting from 1 in every run of
+    /// [`Deduplicate`](crate::Deduplicate).
+    Deduplicate {
+        /// The pass number.
+        pass: usize,
+    },
+    /// Walking the references from the GC roots to find orphans.
+    RemoveOrphans,
+    /// Writing the output.
+    Write,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse => f.write_str("parsing"),
+            Self::Deduplicate { pass } => write!(f, "deduplication pass {pass}"),
+            Self::RemoveOrphans => f.write_str("removing orphans"),
+            Self::Write => f.write_str("writing"),
+        }
+    }
+}
+
+/// A function called with the current [`Phase`] and the fraction of it
+/// done (from 0 to 1), see [`ReduceOptions::progress`].
+///
+/// It is called at the start and end of every phase and regularly in
+/// between, from the thread running the reduction.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(Phase, f64) + Send + Sync>);
+
+impl ProgressCallback {
+    /// Wrap `callback`.
+    pub fn new(callback: impl Fn(Phase, f64) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+impl PartialEq for ProgressCallback {
+    /// Whether both wrap the same function.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A flag to cancel a running reduction from another thread, see
+/// [`ReduceOptions::cancel`]. Clones share the flag.
+///
+/// ```
+/// use stepreduce::{CancellationToken, Error, ReduceOptions, reduce};
+///
+/// let cancel = CancellationToken::new();
+/// let options = ReduceOptions {
+///     cancel: Some(cancel.clone()),
+///     ..Default::default()
+/// };
+/// cancel.cancel(); // e.g. from a GUI thread
+/// assert!(matches!(reduce(b"DATA;\nENDSEC;\n", &options), Err(Error::Cancelled)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A token that is not cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all reductions using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`cancel`](Self::cancel) was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    /// Whether both share the same flag.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The reduction was cancelled; converts into [`Error::Cancelled`].
+#[derive(Debug)]
+pub(crate) struct Cancelled;
+
+impl From<Cancelled> for Error {
+    fn from(_: Cancelled) -> Self {
+        Self::Cancelled
+    }
+}
+
+/// The progress callback and cancellation token of a reduction, if any.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Monitor<'a> {
+    progress: Option<&'a ProgressCallback>,
+    cancel: Option<&'a CancellationToken>,
+}
+
+impl<'a> Monitor<'a> {
+    /// Items processed between two progress reports or cancellation checks.
+    const INTERVAL: usize = 4096;
+
+    pub(crate) fn new(options: &'a ReduceOptions) -> Self {
+        Self {
+            progress: options.progress.as_ref(),
+            cancel: options.cancel.as_ref(),
+        }
+    }
+
+    /// Report that `fraction` of `phase` is done.
+    pub(crate) fn report(&self, phase: Phase, fraction: f64) {
+        if let Some(progress) = self.progress {
+            (progress.0)(phase, fraction);
+        }
+    }
+
+    /// Fail if the reduction was cancelled.
+    pub(crate) fn check(&self) -> Result<(), Cancelled> {
+        match self.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Note that item `done` of `total` of `phase` is being processed:
+    /// every few items, report progress and check for cancellation.
+    pub(crate) fn step(&self, phase: Phase, done: usize, total: usize) -> Result<(), Cancelled> {
+        if !done.is_multiple_of(Self::INTERVAL) {
+            return Ok(());
+        }
+        self.report(phase, done as f64 / total.max(1) as f64);
+        self.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    mod monitor {
+        use super::*;
+
+        #[test]
+        fn step() {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let cancel = CancellationToken::new();
+            let options = ReduceOptions {
+                progress: Some(ProgressCallback::new({
+                    let reports = reports.clone();
+                    move |phase, fraction| reports.lock().unwrap().push((phase, fraction))
+                })),
+                cancel: Some(cancel.clone()),
+                ..Default::default()
+            };
+            let monitor = Monitor::new(&options);
+
+            for i in 0..10_000 {
+                monitor.step(Phase::RemoveOrphans, i, 16_384).unwrap();
+            }
+            assert_eq!(
+                *reports.lock().unwrap(),
+                [
+                    (Phase::RemoveOrphans, 0.0),
+                    (Phase::RemoveOrphans, 0.25),
+                    (Phase::RemoveOrphans, 0.5)
+                ]
+            );
+
+            cancel.cancel();
+            assert!(monitor.step(Phase::RemoveOrphans, 1, 16_384).is_ok());
+            assert!(monitor.step(Phase::RemoveOrphans, 4096, 16_384).is_err());
+            assert!(monitor.check().is_err());
+        }
+    }
+}
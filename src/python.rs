@@ -0,0 +1,167 @@
+//! Python bindings, built as the `stepreduce` extension module by the
+//! `python` feature.
+
+use std::{fs, path::PathBuf};
+
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyBytes};
+
+use crate::{Error, Precision, ReduceOptions};
+
+create_exception!(
+    stepreduce,
+    StepReduceError,
+    PyException,
+    "The reduction failed."
+);
+create_exception!(
+    stepreduce,
+    VerifyError,
+    StepReduceError,
+    "The reduced output is not a lossless quotient of the input."
+);
+create_exception!(
+    stepreduce,
+    LimitError,
+    StepReduceError,
+    "The input exceeds a resource limit."
+);
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Verify(_) => VerifyError::new_err(e.to_string()),
+            Error::Limit(_) => LimitError::new_err(e.to_string()),
+            _ => StepReduceError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// What a reduction merged and removed.
+#[pyclass(name = "ReduceStats", module = "stepreduce", frozen)]
+struct PyReduceStats(crate::ReduceStats);
+
+#[pymethods]
+impl PyReduceStats {
+    /// Number of entities in the input.
+    #[getter]
+    fn input_entities(&self) -> usize {
+        self.0.input_entities
+    }
+
+    /// Number of entities in the output.
+    #[getter]
+    fn output_entities(&self) -> usize {
+        self.0.output_entities
+    }
+
+    /// Number of entities merged into an equal entity.
+    #[getter]
+    fn merged(&self) -> usize {
+        self.0.merged
+    }
+
+    /// Number of unreachable entities removed.
+    #[getter]
+    fn removed(&self) -> usize {
+        self.0.removed
+    }
+
+    /// Number of entity names dropped by merges.
+    #[getter]
+    fn labels_lost(&self) -> usize {
+        self.0.labels_lost
+    }
+
+    /// Whether numbers were written rounded.
+    #[getter]
+    fn quantized(&self) -> bool {
+        self.0.quantized
+    }
+
+    /// Minimum decimal places kept when quantizing, if known.
+    #[getter]
+    fn quantize_min_decimals(&self) -> Option<u32> {
+        self.0.quantize_min_decimals
+    }
+
+    /// Number of rounds of deduplication and orphan removal.
+    #[getter]
+    fn rounds(&self) -> usize {
+        self.0.rounds
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ReduceStats(input_entities={}, output_entities={}, merged={}, removed={})",
+            self.0.input_entities, self.0.output_entities, self.0.merged, self.0.removed
+        )
+    }
+}
+
+fn options(max_decimals: Option<u32>, use_step_precision: bool) -> ReduceOptions {
+    ReduceOptions {
+        precision: max_decimals.map(Precision::DecimalPlaces),
+        use_step_precision,
+        ..Default::default()
+    }
+}
+
+/// Reduce the STEP file content `data` and return the reduced content.
+#[pyfunction]
+#[pyo3(signature = (data, max_decimals=None, use_step_precision=false))]
+fn reduce<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    max_decimals: Option<u32>,
+    use_step_precision: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let output = crate::reduce(data, &options(max_decimals, use_step_precision))?;
+    Ok(PyBytes::new(py, &output))
+}
+
+/// Like `reduce`, but also return the reduction statistics.
+#[pyfunction]
+#[pyo3(signature = (data, max_decimals=None, use_step_precision=false))]
+fn reduce_with_stats<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    max_decimals: Option<u32>,
+    use_step_precision: bool,
+) -> PyResult<(Bound<'py, PyBytes>, PyReduceStats)> {
+    let (output, stats) =
+        crate::reduce_with_stats(data, &options(max_decimals, use_step_precision))?;
+    Ok((PyBytes::new(py, &output), PyReduceStats(stats)))
+}
+
+/// Reduce the STEP file at `input` into `output` (which may be the same
+/// path), without holding the GIL.
+#[pyfunction]
+#[pyo3(signature = (input, output, max_decimals=None, use_step_precision=false))]
+fn reduce_file(
+    py: Python<'_>,
+    input: PathBuf,
+    output: PathBuf,
+    max_decimals: Option<u32>,
+    use_step_precision: bool,
+) -> PyResult<PyReduceStats> {
+    let options = options(max_decimals, use_step_precision);
+    let stats = py.detach(move || -> PyResult<_> {
+        let data = fs::read(&input)?;
+        let (reduced, stats) = crate::reduce_with_stats(&data, &options)?;
+        fs::write(&output, reduced)?;
+        Ok(stats)
+    })?;
+    Ok(PyReduceStats(stats))
+}
+
+#[pymodule]
+fn stepreduce(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(reduce, m)?)?;
+    m.add_function(wrap_pyfunction!(reduce_with_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(reduce_file, m)?)?;
+    m.add_class::<PyReduceStats>()?;
+    m.add("StepReduceError", m.py().get_type::<StepReduceError>())?;
+    m.add("VerifyError", m.py().get_type::<VerifyError>())?;
+    m.add("LimitError", m.py().get_type::<LimitError>())?;
+    Ok(())
+}
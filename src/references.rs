@@ -1,18 +1,56 @@
+//! Hand-written scanners for entity references like `#123`, as a
+//! replacement for the original `REF_PATTERN` regex (`#(\d+)`), which ran on
+//! every line in every pass.
+//!
+//! Unlike the regex, the scanners skip string literals, so `'Part #12'` is
+//! not a reference.
+
 use std::{
     collections::{HashMap, HashSet},
-    sync::LazyLock,
+    ops::Range,
 };
 
-use regex::Regex;
+/// The position after the string literal whose opening `'` is at
+/// `bytes[start]`, or the end of `bytes` if it is not closed. An escaped
+/// quote `''` ends the literal and opens the next one, so it is skipped too.
+pub(crate) fn string_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start + 1..]
+        .iter()
+        .position(|&b| b == b'\'')
+        .map_or(bytes.len(), |end| start + end + 2)
+}
 
-/// Pattern matching STEP entity references like `#123`.
-static REF_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#(\d+)").unwrap());
+/// Iterate over the byte ranges of all `#NNN` references in `rhs` (including
+/// the `#`), outside of string literals.
+pub(crate) fn find_references(rhs: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let bytes = rhs.as_bytes();
+    let mut pos = 0;
+
+    std::iter::from_fn(move || {
+        while let Some(offset) = bytes[pos..].iter().position(|&b| b == b'#' || b == b'\'') {
+            let start = pos + offset;
+            if bytes[start] == b'\'' {
+                pos = string_end(bytes, start);
+                continue;
+            }
+            let digits = bytes[start + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            pos = start + 1 + digits;
+            if digits > 0 {
+                return Some(start..pos);
+            }
+        }
+        pos = bytes.len();
+        None
+    })
+}
 
 /// Collect all entity reference IDs (`#NNN`) from a right-hand side string.
 pub(crate) fn collect_references(rhs: &str) -> HashSet<u32> {
-    REF_PATTERN
-        .captures_iter(rhs)
-        .filter_map(|cap| cap[1].parse::<u32>().ok())
+    find_references(rhs)
+        .filter_map(|m| rhs[m.start + 1..m.end].parse::<u32>().ok())
         .collect()
 }
 
@@ -23,19 +61,16 @@ pub(crate) fn remap_references(rhs: &str, lookup: &HashMap<u32, u32>) -> String
     let mut result = String::with_capacity(rhs.len());
     let mut last_pos = 0;
 
-    for m in REF_PATTERN.find_iter(rhs) {
-        result.push_str(&rhs[last_pos..m.start()]);
-
-        let old_val: u32 = rhs[m.start() + 1..m.end()].parse().unwrap();
-
-        if let Some(&new_val) = lookup.get(&old_val) {
+    for m in find_references(rhs) {
+        // IDs that don't fit a `u32` can't be in `lookup`.
+        if let Ok(old_val) = rhs[m.start + 1..m.end].parse::<u32>()
+            && let Some(&new_val) = lookup.get(&old_val)
+        {
+            result.push_str(&rhs[last_pos..m.start]);
             result.push('#');
             result.push_str(&new_val.to_string());
-        } else {
-            result.push_str(m.as_str());
+            last_pos = m.end;
         }
-
-        last_pos = m.end();
     }
 
     result.push_str(&rhs[last_pos..]);
@@ -46,6 +81,26 @@ pub(crate) fn remap_references(rhs: &str, lookup: &HashMap<u32, u32>) -> String
 mod tests {
     use super::*;
 
+    mod find_references {
+        use super::*;
+
+        fn ranges(s: &str) -> Vec<(usize, usize)> {
+            find_references(s).map(|m| (m.start, m.end)).collect()
+        }
+
+        #[test]
+        fn strings() {
+            assert_eq!(ranges("FOO(#1,'#2',#3)"), [(4, 6), (12, 14)]);
+            assert_eq!(ranges("FOO('it''s #2',#3)"), [(15, 17)]);
+            assert!(ranges("FOO('#2").is_empty());
+        }
+
+        #[test]
+        fn not_references() {
+            assert_eq!(ranges("FOO(#,##1,#x)"), [(7, 9)]);
+        }
+    }
+
     mod collect_references {
         use super::*;
 
@@ -60,6 +115,12 @@ mod tests {
             let refs = collect_references("CARTESIAN_POINT('',0.,1.,2.)");
             assert!(refs.is_empty());
         }
+
+        #[test]
+        fn overflow() {
+            let refs = collect_references("FOO(#99999999999,#2)");
+            assert_eq!(refs, HashSet::from([2]));
+        }
     }
 
     mod remap_references {
@@ -78,5 +139,12 @@ mod tests {
             let result = remap_references("FOO(#1,#2)", &lookup);
             assert_eq!(result, "FOO(#1,#2)");
         }
+
+        #[test]
+        fn strings() {
+            let lookup = HashMap::from([(1, 10)]);
+            let result = remap_references("FOO('#1 ''#1''',#1)", &lookup);
+            assert_eq!(result, "FOO('#1 ''#1''',#10)");
+        }
     }
 }
@@ -0,0 +1,180 @@
+//! Per-entity-type statistics for a STEP file.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    Error, Pipeline, ReduceOptions, StepFile, deduplicate::entity_type_label, pipeline::Trace,
+    references::collect_references,
+};
+
+/// Statistics about all entities of one type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    /// Number of instances.
+    pub count: usize,
+    /// Total size of all instances in bytes (as joined single lines).
+    pub bytes: usize,
+    /// Number of instances that deduplication would merge into another one.
+    pub duplicates: usize,
+    /// Number of instances not reachable from any GC root.
+    pub unreachable: usize,
+}
+
+impl TypeStats {
+    /// Fraction of instances that deduplication would merge (`0.0..=1.0`).
+    pub fn duplicate_ratio(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.duplicates as f64 / self.count as f64
+        }
+    }
+}
+
+/// A histogram of reference counts: degree → number of entities.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Distribution {
+    /// Number of entities for each observed degree.
+    pub histogram: BTreeMap<usize, usize>,
+}
+
+impl Distribution {
+    fn add(&mut self, degree: usize) {
+        *self.histogram.entry(degree).or_insert(0) += 1;
+    }
+
+    /// The largest observed degree.
+    pub fn max(&self) -> usize {
+        self.histogram.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// The mean degree over all entities.
+    pub fn mean(&self) -> f64 {
+        let (total, n) = self
+            .histogram
+            .iter()
+            .fold((0, 0), |(total, n), (&degree, &count)| {
+                (total + degree * count, n + count)
+            });
+        if n == 0 { 0.0 } else { total as f64 / n as f64 }
+    }
+}
+
+/// Statistics about the data section of a STEP file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileStats {
+    /// Per-type statistics, keyed by entity type name. Complex instances are
+    /// keyed by their sorted partial types, e.g. `(LENGTH_UNIT NAMED_UNIT
+    /// SI_UNIT)`.
+    pub types: BTreeMap<String, TypeStats>,
+    /// Distribution of the number of entities referencing each entity.
+    pub fan_in: Distribution,
+    /// Distribution of the number of distinct entities each entity references.
+    pub fan_out: Distribution,
+}
+
+impl FileStats {
+    /// Total number of entities.
+    pub fn entity_count(&self) -> usize {
+        self.types.values().map(|t| t.count).sum()
+    }
+}
+
+/// Summary of what a reduction did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReduceStats {
+    /// Number of entities in the input.
+    pub input_entities: usize,
+    /// Number of entities in the output.
+    pub output_entities: usize,
+    /// Number of entities merged into an equal entity by deduplication.
+    pub merged: usize,
+    /// Number of entities removed as orphans.
+    pub removed: usize,
+    /// Number of merged entities whose non-empty name differed from the
+    /// surviving entity's name, and was therefore lost. Only possible with
+    /// [`NameHandling::Ignore`](crate::NameHandling::Ignore).
+    pub labels_lost: usize,
+    /// Whether REALs in the output were rounded to the effective precision.
+    pub quantized: bool,
+    /// Minimum number of fractional digits kept when quantizing, derived
+    /// from the finest declared uncertainty. Entities of coarser
+    /// representation contexts may keep fewer.
+    pub quantize_min_decimals: Option<u32>,
+    /// Number of rounds of the pipeline's passes until none changed anything
+    /// (1 for the standard passes), at most
+    /// [`ReduceOptions::max_rounds`](crate::ReduceOptions::max_rounds).
+    pub rounds: usize,
+}
+
+/// Collect statistics for a parsed file. The duplicate and reachability
+/// analysis runs the standard [`Pipeline`] with `options` over a copy of it.
+pub(crate) fn collect_stats(file: &StepFile, options: &ReduceOptions) -> Result<FileStats, Error> {
+    let mut trace = Trace::default();
+    Pipeline::standard(options.clone()).run_file_traced(&mut file.clone(), Some(&mut trace))?;
+
+    let mut stats = FileStats::default();
+    let mut fan_in: HashMap<u32, usize> = HashMap::new();
+    let mut ids: Vec<u32> = Vec::with_capacity(file.entities.len());
+
+    for entity in &file.entities {
+        let eid = entity.id();
+        let rhs = entity.body();
+
+        let entry = stats.types.entry(entity_type_label(&rhs)).or_default();
+        entry.count += 1;
+        entry.bytes += entity.to_string().len();
+        if trace.merges.contains_key(&eid) {
+            entry.duplicates += 1;
+        }
+        if !trace.ids.contains_key(&eid) {
+            entry.unreachable += 1;
+        }
+
+        let refs = collect_references(&rhs);
+        stats.fan_out.add(refs.len());
+        for r in refs {
+            *fan_in.entry(r).or_insert(0) += 1;
+        }
+        ids.push(eid);
+    }
+
+    for eid in ids {
+        stats
+            .fan_in
+            .add(fan_in.get(&eid).copied().unwrap_or_default());
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_type() {
+        let input = "\
+DATA;
+#1=APPLICATION_CONTEXT('core');
+#2=PRODUCT_DEFINITION('pd',#1,#3,#4);
+#3=CARTESIAN_POINT('',0.,0.,0.);
+#4=CARTESIAN_POINT('',0.,0.,0.);
+#5=CARTESIAN_POINT('',1.,0.,0.);
+ENDSEC;
+";
+        let file = StepFile::parse(input.as_bytes()).unwrap();
+        let stats = collect_stats(&file, &ReduceOptions::default()).unwrap();
+
+        assert_eq!(stats.entity_count(), 5);
+        let points = &stats.types["CARTESIAN_POINT"];
+        assert_eq!(points.count, 3);
+        assert_eq!(points.duplicates, 1);
+        assert_eq!(points.unreachable, 1);
+        assert_eq!(points.bytes, 3 * "#3=CARTESIAN_POINT('',0.,0.,0.);".len());
+
+        assert_eq!(stats.fan_out.max(), 3);
+        assert_eq!(stats.fan_in.histogram[&0], 2); // #2 and #5
+        assert_eq!(stats.fan_in.histogram[&1], 3);
+    }
+}
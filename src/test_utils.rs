@@ -0,0 +1,6 @@
+//! Helpers shared by the unit tests.
+
+/// Owned data lines, as the text-based passes take them.
+pub(crate) fn lines(raw: &[&str]) -> Vec<String> {
+    raw.iter().map(|l| l.to_string()).collect()
+}
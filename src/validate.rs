@@ -0,0 +1,282 @@
+//! Referential-integrity checks for STEP files.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{model::parse_instances, parse::ParseResult, references::remap_references};
+
+/// Entities every Part 21 header section must contain.
+const REQUIRED_HEADER_ENTITIES: &[&str] = &["FILE_DESCRIPTION", "FILE_NAME", "FILE_SCHEMA"];
+
+/// A single integrity problem found in a STEP file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Issue {
+    /// An entity references an ID that is not defined in the data section.
+    DanglingReference { entity: u32, target: u32 },
+    /// The same instance ID is defined more than once.
+    DuplicateId { entity: u32 },
+    /// An entity references itself.
+    SelfReference { entity: u32 },
+    /// A data section line that is not of the form `#<id>=<entity>;`.
+    UnparseableLine { text: String },
+    /// A mandatory header entity is missing.
+    MissingHeaderEntity { name: &'static str },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DanglingReference { entity, target } => {
+                write!(f, "#{entity} references undefined entity #{target}")
+            }
+            Self::DuplicateId { entity } => write!(f, "#{entity} is defined more than once"),
+            Self::SelfReference { entity } => write!(f, "#{entity} references itself"),
+            Self::UnparseableLine { text } => write!(f, "unparseable line: {text}"),
+            Self::MissingHeaderEntity { name } => write!(f, "header is missing {name}"),
+        }
+    }
+}
+
+impl Issue {
+    /// The issue with the instance IDs mapped through `ids` (as from the
+    /// original ID to the current one), or `None` if an entity it is about
+    /// is gone.
+    fn remapped(&self, ids: &HashMap<u32, u32>) -> Option<Self> {
+        Some(match self {
+            Self::DanglingReference { entity, target } => Self::DanglingReference {
+                entity: *ids.get(entity)?,
+                target: ids.get(target).copied().unwrap_or(*target),
+            },
+            Self::DuplicateId { entity } => Self::DuplicateId {
+                entity: *ids.get(entity)?,
+            },
+            Self::SelfReference { entity } => Self::SelfReference {
+                entity: *ids.get(entity)?,
+            },
+            Self::UnparseableLine { text } => Self::UnparseableLine {
+                text: remap_references(text, ids),
+            },
+            Self::MissingHeaderEntity { .. } => self.clone(),
+        })
+    }
+}
+
+/// The result of validating a STEP file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// All problems found, header issues first, then data section issues in
+    /// file order.
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Return `true` if no issues were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} integrity issue(s):", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n  {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+/// Check that the header section declares all mandatory entities.
+pub(crate) fn validate_header(header: &[String]) -> Vec<Issue> {
+    let text = header.join("\n");
+    REQUIRED_HEADER_ENTITIES
+        .iter()
+        .filter(|name| {
+            !text.match_indices(*name).any(|(pos, _)| {
+                let before = text[..pos].chars().next_back();
+                let after = text[pos + name.len()..].trim_start();
+                !before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && after.starts_with('(')
+            })
+        })
+        .map(|&name| Issue::MissingHeaderEntity { name })
+        .collect()
+}
+
+/// Check data section lines for unparseable lines, duplicate IDs, dangling
+/// references and self-references.
+pub(crate) fn validate_data(lines: &[String]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut instances: Vec<(u32, Vec<u32>)> = Vec::with_capacity(lines.len());
+    let mut defined: HashSet<u32> = HashSet::with_capacity(lines.len());
+    let mut reported_duplicates: HashSet<u32> = HashSet::new();
+
+    for line in lines {
+        let mut entities = Vec::new();
+        if parse_instances(line, &mut entities).is_err() {
+            issues.push(Issue::UnparseableLine { text: line.clone() });
+            continue;
+        }
+        for entity in entities {
+            let eid = entity.id();
+            if !defined.insert(eid) && reported_duplicates.insert(eid) {
+                issues.push(Issue::DuplicateId { entity: eid });
+            }
+            instances.push((eid, entity.references()));
+        }
+    }
+
+    for (eid, mut refs) in instances {
+        refs.sort_unstable();
+        refs.dedup();
+        for target in refs {
+            if target == eid {
+                issues.push(Issue::SelfReference { entity: eid });
+            } else if !defined.contains(&target) {
+                issues.push(Issue::DanglingReference {
+                    entity: eid,
+                    target,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+/// The issues in `output` that have no counterpart in `input`, whose IDs
+/// `ids` maps to those of `output`.
+pub(crate) fn new_issues(
+    input: &[Issue],
+    output: Vec<Issue>,
+    ids: &HashMap<u32, u32>,
+) -> Vec<Issue> {
+    let known: HashSet<Issue> = input.iter().filter_map(|i| i.remapped(ids)).collect();
+    output.into_iter().filter(|i| !known.contains(i)).collect()
+}
+
+/// Validate an already parsed STEP file.
+pub(crate) fn validate_parsed(parsed: &ParseResult) -> ValidationReport {
+    let mut issues = validate_header(&parsed.header);
+    issues.extend(validate_data(&parsed.data));
+    ValidationReport { issues }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::lines;
+
+    mod validate_header {
+        use super::*;
+
+        #[test]
+        fn complete() {
+            let header = lines(&[
+                "ISO-10303-21;",
+                "HEADER;",
+                "FILE_DESCRIPTION(('test'),'2;1');",
+                "FILE_NAME('a.step','',(''),(''),'','','');",
+                "FILE_SCHEMA(('AUTOMOTIVE_DESIGN'));",
+                "ENDSEC;",
+                "DATA;",
+            ]);
+            assert!(validate_header(&header).is_empty());
+        }
+
+        #[test]
+        fn missing() {
+            let header = lines(&[
+                "HEADER;",
+                "FILE_DESCRIPTION(('FILE_NAME'),'2;1');",
+                "ENDSEC;",
+            ]);
+            assert_eq!(
+                validate_header(&header),
+                [
+                    Issue::MissingHeaderEntity { name: "FILE_NAME" },
+                    Issue::MissingHeaderEntity {
+                        name: "FILE_SCHEMA"
+                    },
+                ]
+            );
+        }
+    }
+
+    mod validate_data {
+        use super::*;
+
+        #[test]
+        fn clean() {
+            let data = lines(&[
+                "#1=CARTESIAN_POINT('',(0.,0.,0.));",
+                "#2=VERTEX_POINT('',#1);",
+            ]);
+            assert!(validate_data(&data).is_empty());
+        }
+
+        #[test]
+        fn dangling_and_self_reference() {
+            let data = lines(&["#1=FOO(#1,#7);"]);
+            assert_eq!(
+                validate_data(&data),
+                [
+                    Issue::SelfReference { entity: 1 },
+                    Issue::DanglingReference {
+                        entity: 1,
+                        target: 7
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn duplicate_id() {
+            let data = lines(&["#1=FOO();", "#1=BAR();", "#1=BAZ();"]);
+            assert_eq!(validate_data(&data), [Issue::DuplicateId { entity: 1 }]);
+        }
+
+        #[test]
+        fn unparseable() {
+            let data = lines(&["#1=FOO(;", "garbage;", "#x=FOO();", "/* comment */"]);
+            assert_eq!(validate_data(&data).len(), 3);
+        }
+
+        #[test]
+        fn parens_in_strings() {
+            let data = lines(&["#1=FOO('(');"]);
+            assert!(validate_data(&data).is_empty());
+        }
+    }
+
+    mod new_issues {
+        use super::*;
+
+        #[test]
+        fn remapped() {
+            let input = validate_data(&lines(&["#5=FOO(#9);", "#6=BAR(#6);", "#7=BAZ(;"]));
+            let output = validate_data(&lines(&["#1=FOO(#9);", "#2=BAR(#2);", "#3=BAZ(;"]));
+            let ids = HashMap::from([(5, 1), (6, 2), (7, 3)]);
+            assert!(new_issues(&input, output, &ids).is_empty());
+        }
+
+        #[test]
+        fn fixed_one_broke_another() {
+            let input = validate_data(&lines(&["#1=FOO(#9);", "#2=BAR(#1);"]));
+            let output = validate_data(&lines(&["#2=BAR(#1);"]));
+            let ids = HashMap::from([(2, 2)]);
+            assert_eq!(input.len(), output.len());
+            assert_eq!(
+                new_issues(&input, output, &ids),
+                [Issue::DanglingReference {
+                    entity: 2,
+                    target: 1
+                }]
+            );
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Self-check that a reduction is lossless.
+//!
+//! The reduced entity graph must be a quotient of the original one: every
+//! kept original entity maps to a reduced entity of the same type whose
+//! arguments are equal up to the configured normalization, once all
+//! references are mapped through the same ID map. Entities without an image
+//! (removed orphans) must not be referenced by any kept entity.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    deduplicate::{NameHandling, comparison_key, entity_type_label},
+    normalize::{PrecisionPolicy, entity_name},
+    parse::Instance,
+    references::{collect_references, remap_references},
+};
+
+/// Maximum number of violations collected before verification gives up.
+const MAX_VIOLATIONS: usize = 20;
+
+/// A single way in which the reduced file fails to be a quotient of the
+/// original.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The original entity maps to an ID that does not exist in the output.
+    MissingImage { original: u32, reduced: u32 },
+    /// The original and reduced entity have different types.
+    TypeMismatch {
+        original: u32,
+        reduced: u32,
+        original_type: String,
+        reduced_type: String,
+    },
+    /// The arguments differ after normalization and reference mapping, or a
+    /// name that had to be preserved was lost.
+    ContentMismatch { original: u32, reduced: u32 },
+    /// A kept entity references an entity that was removed.
+    DroppedReference { original: u32, target: u32 },
+    /// An output entity does not correspond to any original entity.
+    NoPreimage { reduced: u32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingImage { original, reduced } => {
+                write!(f, "#{original} maps to #{reduced}, which does not exist")
+            }
+            Self::TypeMismatch {
+                original,
+                reduced,
+                original_type,
+                reduced_type,
+            } => write!(
+                f,
+                "#{original} ({original_type}) maps to #{reduced} ({reduced_type})"
+            ),
+            Self::ContentMismatch { original, reduced } => {
+                write!(f, "#{original} and #{reduced} differ after normalization")
+            }
+            Self::DroppedReference { original, target } => {
+                write!(f, "#{original} references removed entity #{target}")
+            }
+            Self::NoPreimage { reduced } => {
+                write!(f, "#{reduced} has no corresponding original entity")
+            }
+        }
+    }
+}
+
+/// The reduced output is not a quotient of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The violations found (at most the first 20).
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reduction is not lossless:")?;
+        for violation in &self.violations {
+            write!(f, "\n  {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Split instances into an `id → rhs` table, skipping lines without an ID.
+pub(crate) fn entity_table(lines: &[impl Instance]) -> HashMap<u32, &str> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (eid, rhs) = line.instance()?;
+            Some((eid, rhs.trim()))
+        })
+        .collect()
+}
+
+/// Check that `reduced` is a quotient of `original` under `id_map`.
+///
+/// `id_map` maps original entity IDs to output entity IDs; original entities
+/// missing from the map are considered removed. Names must survive the
+/// mapping as far as `names` requires.
+pub(crate) fn verify_quotient(
+    original: &[impl Instance],
+    reduced: &[impl Instance],
+    id_map: &HashMap<u32, u32>,
+    precision: &PrecisionPolicy,
+    names: NameHandling,
+) -> Result<(), VerifyError> {
+    let original = entity_table(original);
+    let reduced = entity_table(reduced);
+
+    let mut violations = Vec::new();
+    let mut hit: HashSet<u32> = HashSet::with_capacity(reduced.len());
+
+    let mut ids: Vec<u32> = original.keys().copied().collect();
+    ids.sort_unstable();
+
+    for eid in ids {
+        if violations.len() >= MAX_VIOLATIONS {
+            break;
+        }
+        let Some(&rid) = id_map.get(&eid) else {
+            continue;
+        };
+        let rhs = original[&eid];
+        let Some(&reduced_rhs) = reduced.get(&rid) else {
+            violations.push(Violation::MissingImage {
+                original: eid,
+                reduced: rid,
+            });
+            continue;
+        };
+        hit.insert(rid);
+
+        let original_type = entity_type_label(rhs);
+        let reduced_type = entity_type_label(reduced_rhs);
+        if original_type != reduced_type {
+            violations.push(Violation::TypeMismatch {
+                original: eid,
+                reduced: rid,
+                original_type,
+                reduced_type,
+            });
+            continue;
+        }
+
+        // Dangling references in the original are left alone by the
+        // reduction, so only references to defined entities can be dropped.
+        let mut dropped: Vec<u32> = collect_references(rhs)
+            .into_iter()
+            .filter(|target| original.contains_key(target) && !id_map.contains_key(target))
+            .collect();
+        if !dropped.is_empty() {
+            dropped.sort_unstable();
+            violations.extend(
+                dropped
+                    .into_iter()
+                    .map(|target| Violation::DroppedReference {
+                        original: eid,
+                        target,
+                    }),
+            );
+            continue;
+        }
+
+        let mapped = remap_references(rhs, id_map);
+        if comparison_key(&mapped, precision, eid) != comparison_key(reduced_rhs, precision, eid)
+            || !names.allows_merge(entity_name(rhs), entity_name(reduced_rhs))
+        {
+            violations.push(Violation::ContentMismatch {
+                original: eid,
+                reduced: rid,
+            });
+        }
+    }
+
+    if violations.is_empty() {
+        let mut orphaned: Vec<u32> = reduced
+            .keys()
+            .filter(|rid| !hit.contains(rid))
+            .copied()
+            .collect();
+        orphaned.sort_unstable();
+        violations.extend(
+            orphaned
+                .into_iter()
+                .take(MAX_VIOLATIONS)
+                .map(|reduced| Violation::NoPreimage { reduced }),
+        );
+    }
+
+    violations.truncate(MAX_VIOLATIONS);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(VerifyError { violations })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::lines;
+
+    #[test]
+    fn accepts_merge() {
+        let original = lines(&[
+            "#1=CARTESIAN_POINT('a',0.,0.,0.)",
+            "#2=CARTESIAN_POINT('b',0.0,0.,0.)",
+            "#3=LINE('',#1,#2)",
+        ]);
+        let reduced = lines(&["#1=CARTESIAN_POINT('a',0.,0.,0.)", "#2=LINE('',#1,#1)"]);
+        let id_map = HashMap::from([(1, 1), (2, 1), (3, 2)]);
+        assert_eq!(
+            verify_quotient(
+                &original,
+                &reduced,
+                &id_map,
+                &PrecisionPolicy::default(),
+                NameHandling::Ignore
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_lost_name() {
+        let original = lines(&[
+            "#1=CARTESIAN_POINT('a',0.,0.,0.)",
+            "#2=CARTESIAN_POINT('b',0.,0.,0.)",
+        ]);
+        let reduced = lines(&["#1=CARTESIAN_POINT('a',0.,0.,0.)"]);
+        let id_map = HashMap::from([(1, 1), (2, 1)]);
+        assert_eq!(
+            verify_quotient(
+                &original,
+                &reduced,
+                &id_map,
+                &PrecisionPolicy::default(),
+                NameHandling::Ignore
+            ),
+            Ok(())
+        );
+        let err = verify_quotient(
+            &original,
+            &reduced,
+            &id_map,
+            &PrecisionPolicy::default(),
+            NameHandling::Preserve,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.violations,
+            [Violation::ContentMismatch {
+                original: 2,
+                reduced: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_content_mismatch() {
+        let original = lines(&[
+            "#1=CARTESIAN_POINT('',0.,0.,0.)",
+            "#2=CARTESIAN_POINT('',1.,0.,0.)",
+        ]);
+        let reduced = lines(&["#1=CARTESIAN_POINT('',0.,0.,0.)"]);
+        let id_map = HashMap::from([(1, 1), (2, 1)]);
+        let err = verify_quotient(
+            &original,
+            &reduced,
+            &id_map,
+            &PrecisionPolicy::default(),
+            NameHandling::Ignore,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.violations,
+            [Violation::ContentMismatch {
+                original: 2,
+                reduced: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_type_mismatch() {
+        let original = lines(&["#1=DIRECTION('',1.,0.,0.)"]);
+        let reduced = lines(&["#1=VECTOR('',1.,0.,0.)"]);
+        let id_map = HashMap::from([(1, 1)]);
+        let err = verify_quotient(
+            &original,
+            &reduced,
+            &id_map,
+            &PrecisionPolicy::default(),
+            NameHandling::Ignore,
+        )
+        .unwrap_err();
+        assert!(matches!(err.violations[0], Violation::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_reference_to_removed_entity() {
+        let original = lines(&["#1=CARTESIAN_POINT('',0.,0.,0.)", "#2=VERTEX_POINT('',#1)"]);
+        let reduced = lines(&["#1=VERTEX_POINT('',#1)"]);
+        let id_map = HashMap::from([(2, 1)]);
+        let err = verify_quotient(
+            &original,
+            &reduced,
+            &id_map,
+            &PrecisionPolicy::default(),
+            NameHandling::Ignore,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.violations,
+            [Violation::DroppedReference {
+                original: 2,
+                target: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn accepts_dangling_reference() {
+        let original = lines(&["#1=VERTEX_POINT('',#7)"]);
+        let reduced = lines(&["#1=VERTEX_POINT('',#7)"]);
+        let id_map = HashMap::from([(1, 1)]);
+        assert_eq!(
+            verify_quotient(
+                &original,
+                &reduced,
+                &id_map,
+                &PrecisionPolicy::default(),
+                NameHandling::Ignore
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_extra_output_entity() {
+        let original = lines(&["#1=CARTESIAN_POINT('',0.,0.,0.)"]);
+        let reduced = lines(&[
+            "#1=CARTESIAN_POINT('',0.,0.,0.)",
+            "#2=DIRECTION('',1.,0.,0.)",
+        ]);
+        let id_map = HashMap::from([(1, 1)]);
+        let err = verify_quotient(
+            &original,
+            &reduced,
+            &id_map,
+            &PrecisionPolicy::default(),
+            NameHandling::Ignore,
+        )
+        .unwrap_err();
+        assert_eq!(err.violations, [Violation::NoPreimage { reduced: 2 }]);
+    }
+}
@@ -0,0 +1,204 @@
+//! JavaScript bindings for `wasm32-unknown-unknown`, enabled by the `wasm`
+//! feature.
+
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::{DEFAULT_ANNOTATION_ENTITIES, Precision, ReduceOptions};
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_REDUCE_OPTIONS: &str = r#"
+export interface ReduceOptions {
+    /** Decimal places for numeric comparison. */
+    maxDecimals?: number;
+    /** Significant digits for numeric comparison. */
+    significantDigits?: number;
+    /** Absolute tolerance for numeric comparison. */
+    tolerance?: number;
+    /** Derive precision from the file's declared uncertainty. */
+    useStepPrecision?: boolean;
+    /** "ignore" (default), "preserve" or "preserve-non-empty". */
+    names?: string;
+    /** Keep styling and property entities that annotate kept entities. */
+    keepAnnotations?: boolean;
+    /** Rewrite numbers to their shortest exact form. */
+    shortenReals?: boolean;
+    /** Write numbers rounded to the effective precision (lossy). */
+    quantize?: boolean;
+    /** Check that the output is a lossless quotient of the input. */
+    verify?: boolean;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "ReduceOptions")]
+    pub type JsReduceOptions;
+}
+
+/// Read property `key` of `object`, or `None` if it is missing.
+fn property(object: &JsValue, key: &str) -> Result<Option<JsValue>, JsError> {
+    let value = Reflect::get(object, &JsValue::from_str(key))
+        .map_err(|_| JsError::new("options must be an object"))?;
+    Ok((!value.is_undefined() && !value.is_null()).then_some(value))
+}
+
+fn bool_property(object: &JsValue, key: &str) -> Result<bool, JsError> {
+    match property(object, key)? {
+        None => Ok(false),
+        Some(value) => value
+            .as_bool()
+            .ok_or_else(|| JsError::new(&format!("{key} must be a boolean"))),
+    }
+}
+
+fn digits_property(object: &JsValue, key: &str) -> Result<Option<u32>, JsError> {
+    let Some(value) = property(object, key)? else {
+        return Ok(None);
+    };
+    match value.as_f64() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 && n <= f64::from(u32::MAX) => Ok(Some(n as u32)),
+        _ => Err(JsError::new(&format!(
+            "{key} must be a non-negative integer"
+        ))),
+    }
+}
+
+/// Convert a JavaScript `ReduceOptions` object.
+fn reduce_options(options: Option<JsReduceOptions>) -> Result<ReduceOptions, JsError> {
+    let Some(options) = options else {
+        return Ok(ReduceOptions::default());
+    };
+    let object: &JsValue = &options;
+    if object.is_undefined() || object.is_null() {
+        return Ok(ReduceOptions::default());
+    }
+
+    let tolerance = match property(object, "tolerance")? {
+        None => None,
+        Some(value) => match value.as_f64() {
+            Some(eps) if eps > 0.0 && eps.is_finite() => Some(Precision::Absolute(eps)),
+            _ => return Err(JsError::new("tolerance must be a positive number")),
+        },
+    };
+    let significant_digits = match digits_property(object, "significantDigits")? {
+        Some(0) => return Err(JsError::new("significantDigits must be a positive integer")),
+        digits => digits.map(Precision::SignificantDigits),
+    };
+    let precisions = [
+        digits_property(object, "maxDecimals")?.map(Precision::DecimalPlaces),
+        significant_digits,
+        tolerance,
+    ];
+    if precisions.iter().flatten().count() > 1 {
+        return Err(JsError::new(
+            "only one of maxDecimals, significantDigits and tolerance can be set",
+        ));
+    }
+    let names = match property(object, "names")? {
+        None => Default::default(),
+        Some(value) => value
+            .as_string()
+            .ok_or_else(|| JsError::new("names must be a string"))?
+            .parse()
+            .map_err(|e: String| JsError::new(&e))?,
+    };
+
+    Ok(ReduceOptions {
+        precision: precisions.into_iter().flatten().next(),
+        use_step_precision: bool_property(object, "useStepPrecision")?,
+        names,
+        annotation_entities: if bool_property(object, "keepAnnotations")? {
+            DEFAULT_ANNOTATION_ENTITIES
+                .iter()
+                .map(|t| t.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        },
+        shorten_reals: bool_property(object, "shortenReals")?,
+        quantize: bool_property(object, "quantize")?,
+        verify: bool_property(object, "verify")?,
+        ..Default::default()
+    })
+}
+
+/// What a reduction merged and removed.
+#[wasm_bindgen(js_name = ReduceStats)]
+pub struct JsReduceStats(crate::ReduceStats);
+
+#[wasm_bindgen(js_class = ReduceStats)]
+impl JsReduceStats {
+    /// Number of entities in the input.
+    #[wasm_bindgen(getter, js_name = inputEntities)]
+    pub fn input_entities(&self) -> usize {
+        self.0.input_entities
+    }
+
+    /// Number of entities in the output.
+    #[wasm_bindgen(getter, js_name = outputEntities)]
+    pub fn output_entities(&self) -> usize {
+        self.0.output_entities
+    }
+
+    /// Number of entities merged into an equal entity.
+    #[wasm_bindgen(getter)]
+    pub fn merged(&self) -> usize {
+        self.0.merged
+    }
+
+    /// Number of unreachable entities removed.
+    #[wasm_bindgen(getter)]
+    pub fn removed(&self) -> usize {
+        self.0.removed
+    }
+
+    /// Number of entity names dropped by merges.
+    #[wasm_bindgen(getter, js_name = labelsLost)]
+    pub fn labels_lost(&self) -> usize {
+        self.0.labels_lost
+    }
+
+    /// Whether numbers were written rounded.
+    #[wasm_bindgen(getter)]
+    pub fn quantized(&self) -> bool {
+        self.0.quantized
+    }
+
+    /// Number of rounds of deduplication and orphan removal.
+    #[wasm_bindgen(getter)]
+    pub fn rounds(&self) -> usize {
+        self.0.rounds
+    }
+}
+
+/// The reduced file and statistics returned by [`reduce`].
+#[wasm_bindgen]
+pub struct ReduceResult {
+    data: Vec<u8>,
+    stats: crate::ReduceStats,
+}
+
+#[wasm_bindgen]
+impl ReduceResult {
+    /// The reduced STEP file content (a copy on every access).
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Uint8Array {
+        Uint8Array::from(self.data.as_slice())
+    }
+
+    /// The reduction statistics.
+    #[wasm_bindgen(getter)]
+    pub fn stats(&self) -> JsReduceStats {
+        JsReduceStats(self.stats.clone())
+    }
+}
+
+/// Reduce the STEP file content `input`. Throws an `Error` on invalid
+/// options or a failed reduction.
+#[wasm_bindgen]
+pub fn reduce(input: &[u8], options: Option<JsReduceOptions>) -> Result<ReduceResult, JsError> {
+    let options = reduce_options(options)?;
+    let (data, stats) = crate::reduce_with_stats(input, &options)?;
+    Ok(ReduceResult { data, stats })
+}
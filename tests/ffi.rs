@@ -0,0 +1,100 @@
+//! Check `include/stepreduce.h` against cbindgen, and compile and run the C
+//! test program in `tests/ffi/` against it and the `cdylib`.
+
+#![cfg(feature = "ffi")]
+
+use std::{
+    env::{
+        self,
+        consts::{DLL_PREFIX, DLL_SUFFIX},
+    },
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Build the `cdylib` with only the `ffi` feature into a target directory of
+/// its own, which neither depends on nor blocks the build running this test,
+/// and return the directory containing it.
+fn build_cdylib() -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "ffi",
+        ])
+        .arg("--manifest-path")
+        .arg(manifest_dir().join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the cdylib failed");
+
+    let lib_dir = target_dir.join("debug");
+    let lib = lib_dir.join(format!("{DLL_PREFIX}stepreduce{DLL_SUFFIX}"));
+    assert!(lib.exists(), "{} is missing", lib.display());
+    lib_dir
+}
+
+#[test]
+fn header_up_to_date() {
+    let crate_dir = manifest_dir();
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::generate_with_config(&crate_dir, config)
+        .unwrap()
+        .write(&mut generated);
+    let header = fs::read(crate_dir.join("include/stepreduce.h")).unwrap();
+    assert!(
+        header == generated,
+        "include/stepreduce.h is out of date, regenerate it with \
+         `cbindgen --config cbindgen.toml --output include/stepreduce.h`"
+    );
+}
+
+#[test]
+fn c_api() {
+    let manifest_dir = manifest_dir();
+    let lib_dir = build_cdylib();
+    let program = lib_dir.join("stepreduce_ffi_test");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/ffi/test_ffi.c"))
+        .arg("-o")
+        .arg(&program)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lstepreduce")
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "compiling tests/ffi/test_ffi.c failed");
+
+    let vector = manifest_dir.join("test-vectors/00000000_290a9120f9f249a7a05cfe9c_step_000.step");
+    let output = Command::new(&program)
+        .arg(&vector)
+        .arg(vector.with_extension("step.min"))
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
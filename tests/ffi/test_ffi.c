@@ -0,0 +1,89 @@
+/* Exercise the C API: stepreduce_test <input.step> <expected.step.min> */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "stepreduce.h"
+
+#define CHECK(cond)                                                       \
+  do {                                                                    \
+    if (!(cond)) {                                                        \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+      return 1;                                                           \
+    }                                                                     \
+  } while (0)
+
+static unsigned char *read_file(const char *path, size_t *len) {
+  FILE *f = fopen(path, "rb");
+  if (!f) {
+    return NULL;
+  }
+  fseek(f, 0, SEEK_END);
+  long size = ftell(f);
+  fseek(f, 0, SEEK_SET);
+  unsigned char *data = malloc(size > 0 ? (size_t)size : 1);
+  *len = fread(data, 1, (size_t)size, f);
+  fclose(f);
+  return data;
+}
+
+int main(int argc, char **argv) {
+  CHECK(argc == 3);
+  size_t input_len, expected_len;
+  unsigned char *input = read_file(argv[1], &input_len);
+  unsigned char *expected = read_file(argv[2], &expected_len);
+  CHECK(input && expected);
+
+  /* Default options reproduce the test vector. */
+  stepreduce_buffer output;
+  CHECK(stepreduce_reduce(input, input_len, NULL, &output) == STEPREDUCE_OK);
+  CHECK(output.len == expected_len);
+  CHECK(memcmp(output.data, expected, expected_len) == 0);
+  stepreduce_buffer_free(&output);
+  CHECK(output.data == NULL && output.len == 0);
+  stepreduce_buffer_free(&output);
+  stepreduce_buffer_free(NULL);
+
+  /* Options. */
+  stepreduce_options options;
+  CHECK(stepreduce_options_init(&options, sizeof options) == STEPREDUCE_OK);
+  CHECK(options.size == sizeof options && options.max_decimals < 0);
+  options.max_decimals = 3;
+  options.use_step_precision = true;
+  options.verify = true;
+  CHECK(stepreduce_reduce(input, input_len, &options, &output) == STEPREDUCE_OK);
+  CHECK(output.len > 0 && output.len <= expected_len);
+  stepreduce_buffer_free(&output);
+
+  /* Errors. */
+  options.max_file_size = 10;
+  CHECK(stepreduce_reduce(input, input_len, &options, &output) == STEPREDUCE_ERROR_LIMIT);
+  CHECK(output.data == NULL && output.len == 0);
+  CHECK(stepreduce_last_error() != NULL);
+  CHECK(strstr(stepreduce_last_error(), "limit") != NULL);
+  CHECK(strcmp(stepreduce_status_string(STEPREDUCE_ERROR_LIMIT), "resource limit exceeded") == 0);
+  CHECK(strcmp(stepreduce_status_string(42), "unknown status") == 0);
+  CHECK(strcmp(stepreduce_status_string(-1), "unknown status") == 0);
+
+  /* A caller built against a header that only had `size` and
+     `max_decimals` gets the defaults for the other fields. */
+  struct {
+    size_t size;
+    int32_t max_decimals;
+  } old_options;
+  CHECK(stepreduce_options_init((stepreduce_options *)&old_options, sizeof old_options) == STEPREDUCE_OK);
+  old_options.max_decimals = 3;
+  CHECK(stepreduce_reduce(input, input_len, (stepreduce_options *)&old_options, &output) == STEPREDUCE_OK);
+  stepreduce_buffer_free(&output);
+  old_options.size = 1;
+  CHECK(stepreduce_reduce(input, input_len, (stepreduce_options *)&old_options, &output) == STEPREDUCE_ERROR_INVALID_ARGUMENT);
+
+  CHECK(stepreduce_reduce(NULL, 5, NULL, &output) == STEPREDUCE_ERROR_NULL_ARGUMENT);
+  CHECK(stepreduce_reduce(input, input_len, NULL, NULL) == STEPREDUCE_ERROR_NULL_ARGUMENT);
+
+  free(input);
+  free(expected);
+  printf("ok\n");
+  return 0;
+}
@@ -5,7 +5,13 @@ use stepreduce::ReduceOptions;
 fn test_reduce(path: &Path) -> datatest_stable::Result<()> {
     let input = fs::read(path)?;
     let expected = fs::read(path.with_extension("step.min"))?;
-    let actual = stepreduce::reduce(&input, &ReduceOptions::default());
+    let actual = stepreduce::reduce(
+        &input,
+        &ReduceOptions {
+            verify: true,
+            ..Default::default()
+        },
+    )?;
     assert_eq!(actual, expected, "mismatch for {}", path.display());
     Ok(())
 }
@@ -0,0 +1,66 @@
+//! Run the JavaScript bindings under Node with `wasm-bindgen-test-runner`.
+
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use js_sys::{Object, Reflect};
+use stepreduce::wasm::{JsReduceOptions, reduce};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const INPUT: &[u8] =
+    include_bytes!("../test-vectors/00000000_290a9120f9f249a7a05cfe9c_step_000.step");
+const EXPECTED: &[u8] =
+    include_bytes!("../test-vectors/00000000_290a9120f9f249a7a05cfe9c_step_000.step.min");
+
+fn options(properties: &[(&str, JsValue)]) -> JsReduceOptions {
+    let object = Object::new();
+    for (key, value) in properties {
+        Reflect::set(&object, &JsValue::from_str(key), value).unwrap();
+    }
+    object.unchecked_into()
+}
+
+#[wasm_bindgen_test]
+fn default_options() {
+    let result = reduce(INPUT, None).unwrap();
+    assert_eq!(result.data().to_vec(), EXPECTED);
+
+    let stats = result.stats();
+    assert!(stats.output_entities() < stats.input_entities());
+    assert!(!stats.quantized());
+}
+
+#[wasm_bindgen_test]
+fn empty_options() {
+    let result = reduce(INPUT, Some(options(&[]))).unwrap();
+    assert_eq!(result.data().to_vec(), EXPECTED);
+}
+
+#[wasm_bindgen_test]
+fn precision() {
+    let options = options(&[
+        ("maxDecimals", JsValue::from(3)),
+        ("useStepPrecision", JsValue::TRUE),
+        ("verify", JsValue::TRUE),
+    ]);
+    let result = reduce(INPUT, Some(options)).unwrap();
+    assert!(result.data().length() as usize <= EXPECTED.len());
+}
+
+#[wasm_bindgen_test]
+fn invalid_options() {
+    for properties in [
+        vec![("maxDecimals", JsValue::from(-1))],
+        vec![("maxDecimals", JsValue::from(1.5))],
+        vec![("significantDigits", JsValue::from(0))],
+        vec![
+            ("maxDecimals", JsValue::from(3)),
+            ("significantDigits", JsValue::from(3)),
+        ],
+        vec![("verify", JsValue::from_str("yes"))],
+        vec![("tolerance", JsValue::from(0))],
+        vec![("names", JsValue::from_str("sometimes"))],
+    ] {
+        assert!(reduce(INPUT, Some(options(&properties))).is_err());
+    }
+}